@@ -1,71 +1,42 @@
 use std::fs;
 
-fn run_program(program: &Vec<i64>) -> Vec<i64> {
-    let mut mem = program.to_vec();
-    let mut idx = 0;
+use intcode::{patched, run_candidate, search_first, Dimension, PatchSets, Program, VM};
 
-    while idx < mem.len() {
-        let opcode = mem[idx];
-        // println!("op {} at {}", opcode, idx);
-        match opcode {
-            1 => {
-                // add
-                let x = mem[idx + 1] as usize;
-                let y = mem[idx + 2] as usize;
-                let dest = mem[idx + 3] as usize;
-                // println!("  add. x: {} y: {} dest: {}", x, y, dest);
-                mem[dest] = mem[x] + mem[y];
-            },
-            2 => {
-                // mul
-                let x = mem[idx + 1] as usize;
-                let y = mem[idx + 2] as usize;
-                let dest = mem[idx + 3] as usize;
-                // println!("  mul. x: {} y: {} dest: {}", x, y, dest);
-                mem[dest] = mem[x] * mem[y];
-            },
-            99 => {
-                // halt
-                break;
-            }
-            _ => panic!("unknown opcode {}", opcode),
-        }
-        idx += 4;
-    }
+// far more than the program needs, so a noun and verb that send it round in
+// circles give up rather than holding up the search
+const BUDGET: u64 = 100_000;
 
-    return mem;
+// run a copy of vm with noun and verb patched in, returning address 0.
+// None if the program faults
+fn run_program(vm: &VM, space: &[Dimension<i64>], noun_verb: &[i64]) -> Option<i64> {
+    let vm = patched(vm, space, noun_verb).ok()?;
+    let (vm, _) = run_candidate(vm, BUDGET).ok()?;
+    Some(vm.peek(0))
 }
 
 fn main() {
-    let input = fs::read_to_string("input.txt").unwrap();
-    let program: Vec<i64> = input
-        .trim()
-        .split(",")
-        .map(|x| {
-            x.parse::<i64>().unwrap()
-        })
-        .collect();
-    
-    // 1202 program alarm
-    let mut copy1 = program.to_vec();
-    copy1[1] = 12;
-    copy1[2] = 2;
-    let output = run_program(&copy1);
+    let program = Program::load("input.txt").unwrap();
 
-    println!("part 1: {}", &output[0]);
+    // every attempt forks this one, sharing its memory until it's written to
+    let vm = program.vm();
+
+    // 1202 program alarm
+    let patches = PatchSets::parse(&fs::read_to_string("patches.txt").unwrap()).unwrap();
+    let mut alarm = vm.clone();
+    patches.apply("alarm", &mut alarm).unwrap();
+    alarm.run(&[]).unwrap();
+    println!("part 1: {}", alarm.peek(0));
 
     let expected = 19690720;
-    let mut copy = program.to_vec();
 
-    for a in 0..99 {
-        for b in 0..99 {
-            copy[1] = a;
-            copy[2] = b;
-            let output = run_program(&copy);
-            if output[0] == expected {
-                println!("part 2: noun = {}, verb = {}, answer = {}", a, b, 100 * a + b);
-                break;
-            }
-        }
+    let space = [
+        Dimension::Patch(1, Box::new(Dimension::Range(0, 99))),
+        Dimension::Patch(2, Box::new(Dimension::Range(0, 99))),
+    ];
+    let found = search_first(&space, |x| run_program(&vm, &space, x), |x| *x == expected);
+
+    if let Some((noun_verb, _)) = found {
+        let (a, b) = (noun_verb[0], noun_verb[1]);
+        println!("part 2: noun = {}, verb = {}, answer = {}", a, b, 100 * a + b);
     }
 }
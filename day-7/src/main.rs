@@ -1,83 +1,81 @@
-use std::fs;
-use itertools::Itertools;
+use std::time::Instant;
 
-mod intcode;
+use intcode::{run_pipeline, search_best, Dimension, NetEvent, Network, Program, Routing, VM};
 
-fn part_one(program: &[i32]) -> i32 {
-  let combinations = (0..=4).permutations(5);
-
-  let mut best: (i32, Vec<i32>) = (0, Vec::new());
-
-  for phase_settings in combinations {
-    // println!("trying {:?}", phase_settings);
+fn part_one(program: &[i64]) -> i64 {
+  let amp = VM::from_program(program);
+  let space = [Dimension::Permutations((0..=4).collect())];
 
+  let best = search_best(&space, |phase_settings| {
     let mut input = 0;
-    for setting in &phase_settings {
-      let mut vm = intcode::VM::from_program(&program);
-      let output = vm.run(&[*setting, input]);
-      input = *output.first()
-        .expect("no output from a vm!");
+    for setting in phase_settings {
+      let mut vm = amp.clone();
+      let output = vm.run(&[*setting, input]).ok()?;
+      input = *output.first()?;
     }
+    Some(input)
+  });
 
-    if input > best.0 {
-      best = (input, phase_settings);
-    }
-  }
+  best.expect("no phase settings worked!").1
+}
 
-  best.0
+// every amp gets its phase first
+fn amps(amp: &VM, phase_settings: &[i64]) -> Vec<VM> {
+  phase_settings
+    .iter()
+    .map(|setting| {
+      let mut vm = amp.clone();
+      vm.push_input(*setting);
+      vm
+    })
+    .collect()
 }
 
-fn part_two(program: &[i32]) -> (i32, Vec<i32>) {
-  let combinations = (5..=9).permutations(5);
-  let mut best: (i32, Vec<i32>) = (0, Vec::new());
+fn part_two(program: &[i64]) -> (i64, Vec<i64>) {
+  let amp = VM::from_program(program);
+  let space = [Dimension::Permutations((5..=9).collect())];
 
-  for phase_settings in combinations {
-    let mut first_run = true;
-    let mut done = false;
-    let mut input = 0;
+  let best = search_best(&space, |phase_settings| {
+    // the first amp gets the 0 signal
+    let mut network = Network::new(amps(&amp, phase_settings), Routing::Ring);
+    network.node_mut(0).push_input(0);
 
-    let mut vms = [
-      intcode::VM::from_program(&program),
-      intcode::VM::from_program(&program),
-      intcode::VM::from_program(&program),
-      intcode::VM::from_program(&program),
-      intcode::VM::from_program(&program),
-    ];
-
-    while !done {
-      for (vm, setting) in vms.iter_mut().zip(&phase_settings) {
-        if first_run {
-          // pass the phase
-          let output = vm.run_until_input(&[*setting, input]);
-          input = *output.first().unwrap();
-        } else {
-          let output = vm.run_until_input(&[input]);
-          input = *output.first().unwrap();
-        }
-      }
-
-      done = vms.iter().all(|x| x.is_halted());
-      first_run = false;
+    match network.run() {
+      NetEvent::Halted => network.last_output(network.len() - 1).copied(),
+      _ => None,
     }
+  });
 
-    if input > best.0 {
-      best = (input, phase_settings);
-    }
-  }
+  let (phase_settings, signal) = best.expect("no phase settings worked!");
+  (signal, phase_settings)
+}
+
+// the same as part two, but with every amp on its own thread rather than
+// taking turns on one
+fn part_two_threaded(program: &[i64]) -> (i64, Vec<i64>) {
+  let amp = VM::from_program(program);
+  let space = [Dimension::Permutations((5..=9).collect())];
+
+  let best = search_best(&space, |phase_settings| {
+    let outputs = run_pipeline(amps(&amp, phase_settings), &[0], true).ok()?;
+    outputs.last().copied()
+  });
 
-  best
+  let (phase_settings, signal) = best.expect("no phase settings worked!");
+  (signal, phase_settings)
 }
 
 fn main() {
-  let input = fs::read_to_string("input.txt").unwrap();
-
-  let program: Vec<i32> = input
-      .trim()
-      .split(",")
-      .map(|x| x.parse::<i32>().unwrap())
-      .collect();
+  let program = Program::load("input.txt").unwrap().words;
 
   println!("running");
   println!("part 1: {}", part_one(&program));
+
+  let start = Instant::now();
   println!("part 2: {:?}", part_two(&program));
+  println!("  network:  {:?}", start.elapsed());
+
+  let start = Instant::now();
+  println!("part 2: {:?}", part_two_threaded(&program));
+  println!("  threaded: {:?}", start.elapsed());
 }
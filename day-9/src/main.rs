@@ -1,40 +1,26 @@
-use std::str::FromStr;
-use std::fs;
-
-mod intcode;
-use intcode::VM;
-
-extern crate num;
-use num::bigint::BigInt;
-use num::{One, FromPrimitive};
+use intcode::Program;
 
 fn main() {
     // let program = "104,1125899906842624,99";
-    let program = fs::read_to_string("input.txt").unwrap();
-
-    let program_bigints: Vec<BigInt> = program
-        .trim()
-        .split(",")
-        .map(|x| BigInt::from_str(x).unwrap())
-        .collect();
+    let program = Program::<i64>::load("input.txt").unwrap();
 
     // part 1
-    let mut vm1 = VM::from_program(&program_bigints);
-    let output1 = vm1.run(&[BigInt::one()]);
+    let mut vm1 = program.vm();
+    let output1 = vm1.run(&[1]).unwrap();
 
     print!("part 1: ");
     for num in output1 {
         print!("{} ", num);
     }
-    println!("");
+    println!();
 
     // part 2
-    let mut vm2 = VM::from_program(&program_bigints);
-    let output2 = vm2.run(&[FromPrimitive::from_i32(2).unwrap()]);
+    let mut vm2 = program.vm();
+    let output2 = vm2.run(&[2]).unwrap();
 
     print!("part 2: ");
     for num in output2 {
         print!("{} ", num);
     }
-    println!("");
+    println!();
 }
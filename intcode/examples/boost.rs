@@ -0,0 +1,64 @@
+// times the day 9 BOOST program, which runs a few hundred thousand
+// instructions in part 2, with the plain loop, with the decode cache, and
+// with the i64 fast path on top of that
+//
+//     cargo run --release --example boost [program]
+
+use std::env;
+use std::time::{Duration, Instant};
+
+use intcode::{Backend, Memory, Program, Word, VM};
+
+use num::bigint::BigInt;
+
+const RUNS: u32 = 20;
+
+fn time<W: Word>(name: &str, program: &[W], backend: Backend, cache: bool, fast: bool) {
+  let mut best = Duration::MAX;
+  let mut steps = 0;
+  let mut output = Vec::new();
+
+  for _ in 0..RUNS {
+    let mut vm = VM::from_memory(Memory::with_backend(backend, program));
+    vm.set_decode_cache(cache);
+    vm.set_fast_path(fast);
+
+    let start = Instant::now();
+    output = vm.run(&[W::from_i64(2)]).unwrap();
+    best = best.min(start.elapsed());
+    steps = vm.steps();
+  }
+
+  let rate = steps as f64 / best.as_secs_f64() / 1_000_000.0;
+  println!(
+    "{:<8} {:<8} {:<11} {:>8.2}ms {:>7.1}M instr/s  output {}",
+    name,
+    format!("{:?}", backend),
+    match (cache, fast) {
+      (false, false) => "-",
+      (true, false) => "cache",
+      (false, true) => "fast",
+      (true, true) => "cache+fast",
+    },
+    best.as_secs_f64() * 1000.0,
+    rate,
+    output[0],
+  );
+}
+
+fn main() {
+  let path = env::args().nth(1).unwrap_or_else(|| "../day-9/input.txt".to_string());
+  let program = Program::<i64>::load(&path).unwrap().words;
+  let big: Vec<BigInt> = program.iter().map(|x| BigInt::from(*x)).collect();
+
+  println!("best of {} runs of {}", RUNS, path);
+  for backend in &[Backend::Dense, Backend::Paged, Backend::Sparse] {
+    for (cache, fast) in &[(false, false), (true, false), (true, true)] {
+      time("i64", &program, *backend, *cache, *fast);
+    }
+  }
+  // there's no fast path for anything but i64
+  for cache in &[false, true] {
+    time("bigint", &big, Backend::Paged, *cache, false);
+  }
+}
@@ -0,0 +1,181 @@
+use std::collections::VecDeque;
+use std::io;
+use std::io::{BufRead, Write};
+
+use crate::error::VmError;
+use crate::io::{Input, Output};
+use crate::vm::VM;
+use crate::word::Word;
+
+// a line of text as input words, one per byte, with the newline the
+// program will be waiting for on the end
+pub fn encode_line<W: Word>(line: &str) -> Vec<W> {
+  let line = line.trim_end_matches(&['\r', '\n'][..]);
+  line.bytes().chain(Some(b'\n')).map(|x| W::from_i64(x as i64)).collect()
+}
+
+// output words as text. anything that isn't ascii, usually a puzzle
+// answer, comes out as a number on a line of its own so it doesn't run into
+// the text around it
+pub fn render<W: Word>(values: &[W]) -> String {
+  values.iter().map(render_one).collect()
+}
+
+fn render_one<W: Word>(value: &W) -> String {
+  match value.to_i64() {
+    Some(x @ 0..=127) => (x as u8 as char).to_string(),
+    _ => format!("{}\n", value),
+  }
+}
+
+// reads a line at a time and hands it to the vm a character at a time.
+// end of input counts as running out, and so does a read error, e.g. a line
+// that isn't utf-8
+pub struct AsciiInput<R> {
+  reader: R,
+  pending: VecDeque<i64>,
+  prompt: Option<String>,
+}
+
+impl<R: BufRead> AsciiInput<R> {
+  pub fn new(reader: R) -> AsciiInput<R> {
+    AsciiInput { reader, pending: VecDeque::new(), prompt: None }
+  }
+
+  // printed to stdout before each line is read, for typing at a terminal
+  pub fn set_prompt(&mut self, prompt: Option<&str>) {
+    self.prompt = prompt.map(|x| x.to_string());
+  }
+}
+
+impl<W: Word, R: BufRead> Input<W> for AsciiInput<R> {
+  fn read(&mut self) -> Option<W> {
+    if self.pending.is_empty() {
+      if let Some(prompt) = &self.prompt {
+        // not being able to show the prompt doesn't stop anyone typing
+        let mut stdout = io::stdout();
+        let _ = write!(stdout, "{}", prompt).and_then(|_| stdout.flush());
+      }
+
+      let mut line = String::new();
+      match self.reader.read_line(&mut line) {
+        Ok(0) | Err(_) => return None,
+        Ok(_) => (),
+      }
+      self.pending.extend(encode_line::<i64>(&line));
+    }
+
+    self.pending.pop_front().map(W::from_i64)
+  }
+}
+
+// writes output as it comes, as render would. if writing fails, e.g. the
+// output is piped into head, the error is kept and nothing more is written
+pub struct AsciiOutput<T> {
+  out: T,
+  error: Option<io::Error>,
+}
+
+impl<T: Write> AsciiOutput<T> {
+  pub fn new(out: T) -> AsciiOutput<T> {
+    AsciiOutput { out, error: None }
+  }
+
+  // the first write that failed, if one has
+  pub fn error(&self) -> Option<&io::Error> {
+    self.error.as_ref()
+  }
+
+  pub fn into_inner(self) -> T {
+    self.out
+  }
+}
+
+impl<W: Word, T: Write> Output<W> for AsciiOutput<T> {
+  fn write(&mut self, value: W) {
+    if self.error.is_none() {
+      self.error = self.out.write_all(render_one(&value).as_bytes()).err();
+    }
+  }
+}
+
+// talk to an ascii program from the terminal until it halts, or until
+// stdin runs out
+pub fn run_ascii<W: Word>(vm: &mut VM<W>) -> Result<(), VmError<W>> {
+  let stdin = io::stdin();
+  let mut input = AsciiInput::new(stdin.lock());
+  input.set_prompt(Some("> "));
+  vm.run_with(&mut input, &mut AsciiOutput::new(io::stdout()))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  use crate::asm::assemble;
+
+  // echoes a line back with every letter shouted, then says how long the
+  // line was
+  const SHOUT: &str = "
+    loop: in -> [c]
+          eq [c] #10 -> [flag]
+          jt [flag] #done
+          add [len] #1 -> [len]
+          lt [c] #97 -> [flag]
+          jt [flag] #print
+          add [c] #-32 -> [c]
+   print: out [c]
+          jt #1 #loop
+    done: out #10
+          add [len] #1000 -> [len]
+          out [len]
+          hlt
+    c:    .data 0
+    len:  .data 0
+    flag: .data 0
+  ";
+
+  #[test]
+  fn test_encode_render() {
+    assert_eq!(encode_line::<i64>("Hi"), [72, 105, 10]);
+    assert_eq!(encode_line::<i64>("Hi\r\n"), [72, 105, 10]);
+    assert_eq!(encode_line::<i64>(""), [10]);
+
+    assert_eq!(render::<i64>(&[72, 105, 10, 19690720]), "Hi\n19690720\n");
+    assert_eq!(render::<i64>(&[128, -1]), "128\n-1\n");
+  }
+
+  #[test]
+  fn test_adapters() {
+    let mut vm = VM::<i64>::from_program(&assemble(SHOUT).unwrap());
+    let mut output = AsciiOutput::new(Vec::new());
+    vm.run_with(&mut AsciiInput::new(&b"hey you\nignored\n"[..]), &mut output).unwrap();
+
+    assert!(vm.is_halted());
+    assert_eq!(String::from_utf8(output.into_inner()).unwrap(), "HEY YOU\n1007\n");
+
+    // the last line doesn't need a newline
+    let mut vm = VM::<i64>::from_program(&assemble(SHOUT).unwrap());
+    let mut output = AsciiOutput::new(Vec::new());
+    vm.run_with(&mut AsciiInput::new(&b"abc"[..]), &mut output).unwrap();
+    assert_eq!(String::from_utf8(output.into_inner()).unwrap(), "ABC\n1003\n");
+
+    // running out of lines leaves the vm waiting
+    let mut output = AsciiOutput::new(Vec::new());
+    let mut vm = VM::<i64>::from_program(&assemble(SHOUT).unwrap());
+    vm.run_with(&mut AsciiInput::new(&b""[..]), &mut output).unwrap();
+    assert!(!vm.is_halted());
+    // so does a line that isn't utf-8
+    let mut vm = VM::<i64>::from_program(&assemble(SHOUT).unwrap());
+    vm.run_with(&mut AsciiInput::new(&b"\xff\xfe\n"[..]), &mut output).unwrap();
+    assert!(!vm.is_halted());
+
+    // output that can't be written is dropped, keeping the first error
+    let mut output = AsciiOutput::new(io::Cursor::new([0u8; 2]));
+    let mut vm = VM::<i64>::from_program(&assemble(SHOUT).unwrap());
+    vm.run_with(&mut AsciiInput::new(&b"hey\n"[..]), &mut output).unwrap();
+    assert!(vm.is_halted());
+    assert_eq!(output.error().map(|x| x.kind()), Some(io::ErrorKind::WriteZero));
+    assert_eq!(&output.into_inner().into_inner(), b"HE");
+  }
+}
@@ -0,0 +1,324 @@
+/*
+= syntax =
+the same mnemonics and params the disassembler prints, one instruction per
+line. ; starts a comment, the -> before a write target is optional
+
+    start:  in -> [x]
+            eq [x] #8 -> [flag]
+            jt [flag] #equal
+            out #999
+            hlt
+    equal:  out #1000
+            hlt
+    x:      .data 0
+    flag:   .data 0
+
+= params =
+#5      immediate
+[5]     position
+rel[-1] relative
+
+anywhere a number can go, a label (optionally with +n or -n on the end) can
+be used instead, e.g. #equal or [x+1]
+*/
+
+use std::collections::HashMap;
+use std::error;
+use std::fmt;
+
+use crate::instruction::{Instruction, Mode, Opcode, Param};
+use crate::word::Word;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AsmErrorKind {
+  UnknownMnemonic(String),
+  UnknownDirective(String),
+  WrongParamCount { expected: usize, found: usize },
+  BadParam(String),
+  BadNumber(String),
+  ImmediateWriteTarget,
+  UnknownLabel(String),
+  DuplicateLabel(String),
+}
+
+// line numbers start at 1
+#[derive(Debug, Clone, PartialEq)]
+pub struct AsmError {
+  pub line: usize,
+  pub kind: AsmErrorKind,
+}
+
+impl fmt::Display for AsmError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "line {}: ", self.line)?;
+    match &self.kind {
+      AsmErrorKind::UnknownMnemonic(x) => write!(f, "unknown mnemonic {}", x),
+      AsmErrorKind::UnknownDirective(x) => write!(f, "unknown directive {}", x),
+      AsmErrorKind::WrongParamCount { expected, found } => {
+        write!(f, "expected {} params, found {}", expected, found)
+      },
+      AsmErrorKind::BadParam(x) => write!(f, "bad param {}", x),
+      AsmErrorKind::BadNumber(x) => write!(f, "bad number {}", x),
+      AsmErrorKind::ImmediateWriteTarget => write!(f, "write target can't be immediate"),
+      AsmErrorKind::UnknownLabel(x) => write!(f, "unknown label {}", x),
+      AsmErrorKind::DuplicateLabel(x) => write!(f, "label {} defined twice", x),
+    }
+  }
+}
+
+impl error::Error for AsmError {}
+
+// a line with its label and comment taken off
+enum Statement<'a> {
+  Instruction(Opcode, Vec<&'a str>),
+  Data(Vec<&'a str>),
+}
+
+impl<'a> Statement<'a> {
+  fn size(&self) -> usize {
+    match self {
+      Statement::Instruction(opcode, _) => opcode.param_count() + 1,
+      Statement::Data(values) => values.len(),
+    }
+  }
+}
+
+fn is_label(name: &str) -> bool {
+  let mut chars = name.chars();
+  match chars.next() {
+    Some(c) if c.is_ascii_alphabetic() || c == '_' => (),
+    _ => return false,
+  }
+  chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn parse_statement(text: &str) -> Result<Option<Statement<'_>>, AsmErrorKind> {
+  if text.is_empty() {
+    return Ok(None);
+  }
+
+  // the directive has to be a whole word, so .database isn't .data
+  let data = text.strip_prefix(".data").filter(|x| x.is_empty() || x.starts_with(char::is_whitespace));
+  if let Some(rest) = data {
+    let values = rest.split(',').map(|x| x.trim()).collect();
+    return Ok(Some(Statement::Data(values)));
+  }
+
+  if text.starts_with('.') {
+    let name = text.split_whitespace().next().unwrap_or(text);
+    return Err(AsmErrorKind::UnknownDirective(name.to_string()));
+  }
+
+  let mut tokens = text
+    .split(|c: char| c.is_whitespace() || c == ',')
+    .filter(|x| !x.is_empty() && *x != "->");
+
+  let mnemonic = tokens.next().unwrap_or("");
+  let opcode = match Opcode::from_mnemonic(mnemonic) {
+    Some(x) => x,
+    None => return Err(AsmErrorKind::UnknownMnemonic(mnemonic.to_string())),
+  };
+
+  let params: Vec<&str> = tokens.collect();
+  if params.len() != opcode.param_count() {
+    return Err(AsmErrorKind::WrongParamCount { expected: opcode.param_count(), found: params.len() });
+  }
+
+  Ok(Some(Statement::Instruction(opcode, params)))
+}
+
+struct Assembler {
+  labels: HashMap<String, usize>,
+}
+
+impl Assembler {
+  // a number, a label, or a label with an offset
+  fn value<W: Word>(&self, text: &str) -> Result<W, AsmErrorKind> {
+    let (name, offset) = match text.find(['+', '-']) {
+      Some(idx) if idx > 0 => (&text[..idx], &text[idx..]),
+      _ => (text, ""),
+    };
+
+    if !is_label(name) {
+      return text
+        .trim_start_matches('+')
+        .parse::<W>()
+        .map_err(|_| AsmErrorKind::BadNumber(text.to_string()));
+    }
+
+    let addr = match self.labels.get(name) {
+      Some(x) => W::from_i64(*x as i64),
+      None => return Err(AsmErrorKind::UnknownLabel(name.to_string())),
+    };
+
+    if offset.is_empty() {
+      return Ok(addr);
+    }
+
+    let offset: W = offset
+      .trim_start_matches('+')
+      .parse()
+      .map_err(|_| AsmErrorKind::BadNumber(text.to_string()))?;
+    addr.checked_add(&offset).ok_or_else(|| AsmErrorKind::BadNumber(text.to_string()))
+  }
+
+  fn param<W: Word>(&self, text: &str) -> Result<Param<W>, AsmErrorKind> {
+    let (mode, inner) = if let Some(x) = text.strip_prefix('#') {
+      (Mode::Immediate, x)
+    } else if let Some(x) = text.strip_prefix("rel[").and_then(|x| x.strip_suffix(']')) {
+      (Mode::Relative, x)
+    } else if let Some(x) = text.strip_prefix('[').and_then(|x| x.strip_suffix(']')) {
+      (Mode::Position, x)
+    } else {
+      return Err(AsmErrorKind::BadParam(text.to_string()));
+    };
+
+    Ok(Param::new(mode, self.value(inner)?))
+  }
+
+  fn emit<W: Word>(&self, statement: &Statement, out: &mut Vec<W>) -> Result<(), AsmErrorKind> {
+    match statement {
+      Statement::Instruction(opcode, params) => {
+        let params = params
+          .iter()
+          .map(|x| self.param(x))
+          .collect::<Result<Vec<Param<W>>, AsmErrorKind>>()?;
+
+        if opcode.writes() && params.last().map(|x| x.mode()) == Some(Mode::Immediate) {
+          return Err(AsmErrorKind::ImmediateWriteTarget);
+        }
+
+        out.extend(Instruction { opcode: *opcode, params }.encode());
+      },
+      Statement::Data(values) => {
+        for value in values {
+          out.push(self.value(value)?);
+        }
+      },
+    }
+    Ok(())
+  }
+}
+
+// turn assembly source into a program ready for VM::from_program
+pub fn assemble<W: Word>(source: &str) -> Result<Vec<W>, AsmError> {
+  let mut assembler = Assembler { labels: HashMap::new() };
+  let mut statements = Vec::new();
+  let mut addr = 0;
+
+  // first pass, find out where every label is
+  for (i, line) in source.lines().enumerate() {
+    let line_no = i + 1;
+    let error = |kind| AsmError { line: line_no, kind };
+
+    let mut text = line.split(';').next().unwrap_or("").trim();
+
+    while let Some(idx) = text.find(':') {
+      let name = text[..idx].trim();
+      if !is_label(name) {
+        break;
+      }
+
+      if assembler.labels.insert(name.to_string(), addr).is_some() {
+        return Err(error(AsmErrorKind::DuplicateLabel(name.to_string())));
+      }
+      text = text[idx + 1..].trim();
+    }
+
+    if let Some(statement) = parse_statement(text).map_err(error)? {
+      addr += statement.size();
+      statements.push((line_no, statement));
+    }
+  }
+
+  // second pass, spit out the words now every label has an address
+  let mut program = Vec::with_capacity(addr);
+  for (line, statement) in &statements {
+    assembler
+      .emit(statement, &mut program)
+      .map_err(|kind| AsmError { line: *line, kind })?;
+  }
+
+  Ok(program)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  use crate::disasm::{disassemble, label};
+  use crate::vm::VM;
+
+  const COMPARATOR: &str = "
+    ; 999 if input < 8, 1000 if == 8, 1001 if > 8
+    start:  in -> [x]
+            lt [x] #8 -> [flag]
+            jt [flag] #below
+            eq [x] #8 -> [flag]
+            jt [flag] #equal
+            out #1001
+            hlt
+    below:  out #999
+            hlt
+    equal:  out #1000
+            hlt
+    x:      .data 0
+    flag:   .data 0
+  ";
+
+  fn run(program: &[i64], input: &[i64]) -> Vec<i64> {
+    VM::from_program(program).run(input).unwrap()
+  }
+
+  #[test]
+  fn test_assemble() {
+    let program = assemble::<i64>("add [4] #3 -> rel[+2]\nin rel[-1], hlt").unwrap_err();
+    assert_eq!(program, AsmError { line: 2, kind: AsmErrorKind::WrongParamCount { expected: 1, found: 2 } });
+
+    let program: Vec<i64> = assemble("add [4] #3 -> rel[+2]\nin rel[-1]\nhlt").unwrap();
+    assert_eq!(program, [21001, 4, 3, 2, 203, -1, 99]);
+  }
+
+  #[test]
+  fn test_labels() {
+    let program: Vec<i64> = assemble(COMPARATOR).unwrap();
+    assert_eq!(run(&program, &[7]), [999]);
+    assert_eq!(run(&program, &[8]), [1000]);
+    assert_eq!(run(&program, &[9]), [1001]);
+
+    let program: Vec<i64> = assemble("a: b: .data a+2, b-1, c\nc: hlt").unwrap();
+    assert_eq!(program, [2, -1, 3, 99]);
+  }
+
+  #[test]
+  fn test_errors() {
+    let error = |source: &str| assemble::<i64>(source).unwrap_err();
+
+    assert_eq!(error("nop").kind, AsmErrorKind::UnknownMnemonic("nop".to_string()));
+    assert_eq!(error(".org 5").kind, AsmErrorKind::UnknownDirective(".org".to_string()));
+    assert_eq!(error(".data5").kind, AsmErrorKind::UnknownDirective(".data5".to_string()));
+    assert_eq!(error(".database 1").kind, AsmErrorKind::UnknownDirective(".database".to_string()));
+    assert_eq!(error("out 5").kind, AsmErrorKind::BadParam("5".to_string()));
+    assert_eq!(error("out #five").kind, AsmErrorKind::UnknownLabel("five".to_string()));
+    assert_eq!(error("out #5x").kind, AsmErrorKind::BadNumber("5x".to_string()));
+    assert_eq!(error("in #5").kind, AsmErrorKind::ImmediateWriteTarget);
+    assert_eq!(error("a: hlt\n\na: hlt"), AsmError { line: 3, kind: AsmErrorKind::DuplicateLabel("a".to_string()) });
+  }
+
+  #[test]
+  fn test_round_trip() {
+    // whatever the disassembler prints should assemble back to the program
+    let program: Vec<i64> = assemble(COMPARATOR).unwrap();
+    let listing = disassemble(&program);
+
+    let mut source = String::new();
+    for line in &listing.lines {
+      if listing.labels.contains(&line.addr) {
+        source += &format!("{}:\n", label(line.addr));
+      }
+      source += &format!("{}\n", listing.render(line));
+    }
+
+    assert_eq!(assemble::<i64>(&source), Ok(program));
+  }
+}
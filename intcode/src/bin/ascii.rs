@@ -0,0 +1,45 @@
+use std::env;
+use std::fs;
+use std::process;
+
+use intcode::{encode_line, run_ascii, Program};
+
+// run an ascii program at the terminal. anything in script is typed in
+// first, a line at a time, e.g. the moves to get back to where you were
+fn main() {
+  let args: Vec<String> = env::args().skip(1).collect();
+  if args.is_empty() || args.len() > 2 {
+    eprintln!("usage: ascii <program> [script]");
+    process::exit(1);
+  }
+
+  let mut vm = match Program::<i64>::load(&args[0]) {
+    Ok(x) => x.vm(),
+    Err(err) => {
+      eprintln!("{}: {}", args[0], err);
+      process::exit(1);
+    },
+  };
+
+  if let Some(path) = args.get(1) {
+    let script = match fs::read_to_string(path) {
+      Ok(x) => x,
+      Err(err) => {
+        eprintln!("{}: {}", path, err);
+        process::exit(1);
+      },
+    };
+    for line in script.lines() {
+      vm.push_inputs(&encode_line(line));
+    }
+  }
+
+  match run_ascii(&mut vm) {
+    Ok(()) if vm.is_halted() => (),
+    Ok(()) => println!("ran out of input!"),
+    Err(err) => {
+      eprintln!("error: {}", err);
+      process::exit(1);
+    },
+  }
+}
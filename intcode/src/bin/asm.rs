@@ -0,0 +1,33 @@
+use std::env;
+use std::fs;
+use std::process;
+
+use intcode::assemble;
+
+fn main() {
+  let path = match env::args().nth(1) {
+    Some(x) => x,
+    None => {
+      eprintln!("usage: asm <source>");
+      process::exit(1);
+    },
+  };
+
+  let source = match fs::read_to_string(&path) {
+    Ok(x) => x,
+    Err(err) => {
+      eprintln!("{}: {}", path, err);
+      process::exit(1);
+    },
+  };
+  match assemble::<i64>(&source) {
+    Ok(program) => {
+      let words: Vec<String> = program.iter().map(|x| x.to_string()).collect();
+      println!("{}", words.join(","));
+    },
+    Err(err) => {
+      eprintln!("{}: {}", path, err);
+      process::exit(1);
+    },
+  }
+}
@@ -0,0 +1,26 @@
+use std::env;
+use std::process;
+
+use intcode::{Cfg, Program};
+
+// print a program's control flow graph as graphviz, e.g.
+//     cfg input.txt | dot -Tsvg > cfg.svg
+fn main() {
+  let path = match env::args().nth(1) {
+    Some(x) => x,
+    None => {
+      eprintln!("usage: cfg <program>");
+      process::exit(1);
+    },
+  };
+
+  let program = match Program::<i64>::load(&path) {
+    Ok(x) => x.words,
+    Err(err) => {
+      eprintln!("{}: {}", path, err);
+      process::exit(1);
+    },
+  };
+
+  print!("{}", Cfg::build(&program).to_dot());
+}
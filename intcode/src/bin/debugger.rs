@@ -0,0 +1,254 @@
+use std::collections::BTreeSet;
+use std::env;
+use std::fs;
+use std::io;
+use std::io::Write;
+use std::process;
+
+use intcode::{disassemble_linear, Debugger, Listing, Opcode, Program, Snapshot, State, Stop, VM};
+
+const HELP: &str = "\
+s [n]           step n instructions (default 1)
+c               continue until something interesting happens
+b <addr>        toggle a breakpoint on an address
+bo <mnemonic>   toggle a breakpoint on an opcode, e.g. bo out
+w <addr>        toggle a watchpoint on a memory cell
+l               list breakpoints and watchpoints
+r               show registers
+m <addr> [n]    dump n memory cells (default 16)
+x [addr] [n]    disassemble n instructions (default 8, from ip)
+i <values...>   queue up input values
+p <addr> <val>  poke a value into memory
+save <file>     save the vm, as json if the file ends in .json
+load <file>     load a vm saved with save
+q               quit";
+
+fn parse<T: std::str::FromStr>(arg: Option<&&str>, default: Option<T>) -> Option<T> {
+  match arg {
+    Some(x) => x.parse().ok(),
+    None => default,
+  }
+}
+
+// count instructions from start, only copying out the cells they could
+// take up rather than the whole of memory
+fn listing(dbg: &Debugger, start: usize, count: usize) -> Listing<i64> {
+  let end = start.saturating_add(count.saturating_mul(4));
+  let mut listing = disassemble_linear(&dbg.vm.memory().slice(start, end), 0, count);
+  for line in &mut listing.lines {
+    line.addr += start;
+  }
+  listing
+}
+
+fn show_current(dbg: &Debugger) {
+  let listing = listing(dbg, dbg.vm.instruction_ptr(), 1);
+  if let Some(line) = listing.lines.first() {
+    println!("=> {:>6}: {}", line.addr, listing.render(line));
+  }
+}
+
+fn show_stop(dbg: &mut Debugger, stop: Stop<i64>) {
+  for x in dbg.output.drain(..) {
+    println!("output: {}", x);
+  }
+
+  match stop {
+    Stop::Stepped => (),
+    Stop::Breakpoint(addr) => println!("breakpoint at {}", addr),
+    Stop::OpcodeBreak(opcode) => println!("breakpoint on {}", opcode),
+    Stop::Watch { addr, old, new } => println!("[{}] changed {} -> {}", addr, old, new),
+    Stop::State(State::AwaitingInput) => println!("waiting for input, use i to give it some"),
+    Stop::State(State::Halted) => println!("halted"),
+    Stop::State(State::Faulted(err)) => println!("error: {}", err),
+    Stop::State(State::Output(_)) => (),
+  }
+
+  show_current(dbg);
+}
+
+fn toggle<T: Ord>(set: &mut BTreeSet<T>, value: T) -> bool {
+  if set.remove(&value) {
+    false
+  } else {
+    set.insert(value);
+    true
+  }
+}
+
+// returns false when it's time to quit
+fn command(dbg: &mut Debugger, line: &str) -> bool {
+  let words: Vec<&str> = line.split_whitespace().collect();
+  let args = &words[1.min(words.len())..];
+
+  match words.first().copied() {
+    None => (),
+    Some("q") => return false,
+    Some("h") | Some("?") => println!("{}", HELP),
+    Some("s") => match parse(args.first(), Some(1)) {
+      Some(n) if n > 0 => {
+        let stop = dbg.run(Some(n));
+        show_stop(dbg, stop);
+      },
+      _ => println!("usage: s [n]"),
+    },
+    Some("c") => {
+      let stop = dbg.run(None);
+      show_stop(dbg, stop);
+    },
+    Some("b") => match parse(args.first(), None) {
+      Some(addr) => {
+        let on = toggle(&mut dbg.breakpoints, addr);
+        println!("breakpoint at {} {}", addr, if on { "on" } else { "off" });
+      },
+      None => println!("usage: b <addr>"),
+    },
+    Some("bo") => match args.first().and_then(|x| Opcode::from_mnemonic(x)) {
+      Some(opcode) => {
+        let on = if dbg.opcode_breaks.remove(&opcode) {
+          false
+        } else {
+          dbg.opcode_breaks.insert(opcode);
+          true
+        };
+        println!("breakpoint on {} {}", opcode, if on { "on" } else { "off" });
+      },
+      None => println!("usage: bo <mnemonic>"),
+    },
+    Some("w") => match parse(args.first(), None) {
+      Some(addr) => {
+        if dbg.watches.remove(&addr).is_some() {
+          println!("watch on [{}] off", addr);
+        } else {
+          dbg.watch(addr);
+          println!("watch on [{}] on", addr);
+        }
+      },
+      None => println!("usage: w <addr>"),
+    },
+    Some("l") => {
+      println!("breakpoints: {:?}", dbg.breakpoints);
+      let opcodes: Vec<&str> = dbg.opcode_breaks.iter().map(|x| x.mnemonic()).collect();
+      println!("opcodes:     {:?}", opcodes);
+      println!("watches:     {:?}", dbg.watches.keys().collect::<Vec<_>>());
+    },
+    Some("r") => {
+      println!("ip:     {}", dbg.vm.instruction_ptr());
+      println!("rb:     {}", dbg.vm.relative_base());
+      println!("halted: {}", dbg.vm.is_halted());
+      println!("input:  {:?}", dbg.vm.pending_input());
+    },
+    Some("m") => match (parse(args.first(), None), parse(args.get(1), Some(16))) {
+      (Some(start), Some(count)) => {
+        let start: usize = start;
+        let end: usize = start.saturating_add(count);
+        for row in (start..end).step_by(8) {
+          let cells: Vec<String> = (row..end.min(row.saturating_add(8)))
+            .map(|addr| format!("{:>8}", dbg.vm.peek(addr)))
+            .collect();
+          println!("{:>6}: {}", row, cells.join(" "));
+        }
+      },
+      _ => println!("usage: m <addr> [n]"),
+    },
+    Some("x") => match (parse(args.first(), Some(dbg.vm.instruction_ptr())), parse(args.get(1), Some(8))) {
+      (Some(start), Some(count)) => {
+        let listing = listing(dbg, start, count);
+        for line in &listing.lines {
+          let marker = if line.addr == dbg.vm.instruction_ptr() { "=>" } else { "  " };
+          println!("{} {:>6}: {}", marker, line.addr, listing.render(line));
+        }
+      },
+      _ => println!("usage: x [addr] [n]"),
+    },
+    Some("i") => {
+      let values: Option<Vec<i64>> = args.iter().map(|x| x.parse().ok()).collect();
+      match values {
+        Some(values) if !values.is_empty() => dbg.vm.push_inputs(&values),
+        _ => println!("usage: i <values...>"),
+      }
+    },
+    Some("p") => match (parse(args.first(), None), parse(args.get(1), None)) {
+      (Some(addr), Some(value)) => {
+        if let Err(err) = dbg.vm.poke(addr, value) {
+          println!("error: {}", err);
+        }
+      },
+      _ => println!("usage: p <addr> <value>"),
+    },
+    Some("save") => match args.first() {
+      Some(path) => {
+        let snapshot = dbg.vm.snapshot();
+        let result = if path.ends_with(".json") {
+          fs::write(path, snapshot.to_json())
+        } else {
+          fs::write(path, snapshot.to_bytes())
+        };
+
+        match result {
+          Ok(()) => println!("saved to {}", path),
+          Err(err) => println!("error saving {}: {}", path, err),
+        }
+      },
+      None => println!("usage: save <file>"),
+    },
+    Some("load") => match args.first() {
+      Some(path) => {
+        let loaded = match fs::read(path) {
+          Ok(bytes) if path.ends_with(".json") => {
+            Snapshot::from_json(&String::from_utf8_lossy(&bytes)).map_err(|x| x.to_string())
+          },
+          Ok(bytes) => Snapshot::from_bytes(&bytes).map_err(|x| x.to_string()),
+          Err(err) => Err(err.to_string()),
+        };
+
+        match loaded {
+          Ok(snapshot) => {
+            dbg.restore(&snapshot);
+            show_current(dbg);
+          },
+          Err(err) => println!("error loading {}: {}", path, err),
+        }
+      },
+      None => println!("usage: load <file>"),
+    },
+    Some(x) => println!("unknown command {}, h for help", x),
+  }
+
+  true
+}
+
+fn main() {
+  let path = match env::args().nth(1) {
+    Some(x) => x,
+    None => {
+      eprintln!("usage: debugger <program>");
+      process::exit(1);
+    },
+  };
+
+  let program = match Program::<i64>::load(&path) {
+    Ok(x) => x.words,
+    Err(err) => {
+      eprintln!("{}: {}", path, err);
+      process::exit(1);
+    },
+  };
+
+  let mut dbg = Debugger::new(VM::from_program(&program));
+  show_current(&dbg);
+
+  loop {
+    print!("dbg> ");
+    io::stdout().flush().expect("error flushing stdout!");
+
+    let mut line = String::new();
+    if io::stdin().read_line(&mut line).expect("error reading stdin!") == 0 {
+      break;
+    }
+
+    if !command(&mut dbg, &line) {
+      break;
+    }
+  }
+}
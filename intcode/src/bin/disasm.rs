@@ -0,0 +1,24 @@
+use std::env;
+use std::process;
+
+use intcode::{disassemble, Program};
+
+fn main() {
+  let path = match env::args().nth(1) {
+    Some(x) => x,
+    None => {
+      eprintln!("usage: disasm <program>");
+      process::exit(1);
+    },
+  };
+
+  let program = match Program::<i64>::load(&path) {
+    Ok(x) => x.words,
+    Err(err) => {
+      eprintln!("{}: {}", path, err);
+      process::exit(1);
+    },
+  };
+
+  print!("{}", disassemble(&program));
+}
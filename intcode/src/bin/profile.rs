@@ -0,0 +1,49 @@
+use std::env;
+use std::process;
+
+use intcode::{profile, Program};
+
+const TOP: usize = 10;
+
+// run a program with the given inputs and say where it spent its time,
+// with -a for the whole disassembly annotated with counts instead
+fn main() {
+  let mut args: Vec<String> = env::args().skip(1).collect();
+  let annotate = args.first().is_some_and(|x| x == "-a");
+  if annotate {
+    args.remove(0);
+  }
+
+  if args.is_empty() {
+    eprintln!("usage: profile [-a] <program> [inputs...]");
+    process::exit(1);
+  }
+
+  let program = match Program::<i64>::load(&args[0]) {
+    Ok(x) => x,
+    Err(err) => {
+      eprintln!("{}: {}", args[0], err);
+      process::exit(1);
+    },
+  };
+
+  let input: Vec<i64> = match args[1..].iter().map(|x| x.parse()).collect() {
+    Ok(x) => x,
+    Err(_) => {
+      eprintln!("inputs have to be numbers");
+      process::exit(1);
+    },
+  };
+
+  let (result, profile) = profile(&mut program.vm(), &input);
+  match result {
+    Ok(output) => println!("output: {:?}\n", output),
+    Err(err) => println!("error: {}\n", err),
+  }
+
+  if annotate {
+    print!("{}", profile.annotate(&program.words));
+  } else {
+    print!("{}", profile.report(&program.words, TOP));
+  }
+}
@@ -0,0 +1,270 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+
+use crate::disasm::{find_code, jump_targets, label, stored_constant};
+use crate::instruction::{Instruction, Opcode, Param};
+use crate::word::Word;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EdgeKind {
+  // running off the end of a block into the next one, or a conditional
+  // jump not being taken
+  FallThrough,
+  Jump,
+  // an unconditional jump with the address after it stored first, which is
+  // how compiled intcode calls a function
+  Call,
+  // from a call to where the function comes back to
+  ReturnSite,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Edge {
+  pub to: usize,
+  pub kind: EdgeKind,
+}
+
+// a run of instructions that's only ever entered at the top and left at
+// the bottom
+#[derive(Debug, Clone, PartialEq)]
+pub struct Block<W> {
+  pub start: usize,
+  pub instructions: Vec<(usize, Instruction<W>)>,
+  pub edges: Vec<Edge>,
+  // the last instruction is a jump to somewhere only known at run time,
+  // almost always a function returning to whoever called it
+  pub indirect: bool,
+  pub halts: bool,
+  // every arb in the block, e.g. a function making room on the stack
+  pub relative_base: Vec<(usize, Param<W>)>,
+}
+
+impl<W: Word> Block<W> {
+  // one past the last instruction
+  pub fn end(&self) -> usize {
+    self.instructions.last().map_or(self.start, |(addr, instr)| addr + instr.size())
+  }
+}
+
+// the control flow graph of everything reachable from address 0, found the
+// same way disassemble finds code
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cfg<W> {
+  pub blocks: BTreeMap<usize, Block<W>>,
+  // address 0, and everything that gets called
+  pub functions: BTreeSet<usize>,
+}
+
+impl<W: Word> Cfg<W> {
+  pub fn build(program: &[W]) -> Cfg<W> {
+    let (code, targets) = find_code(program);
+    let parse = |addr: usize| match Instruction::parse(&program[addr..]) {
+      Some(Ok(x)) => x,
+      _ => unreachable!("find_code only returns instructions that decode"),
+    };
+
+    // a block starts at the entry point, anywhere jumped to, after any jump
+    // and wherever code picks up again after a gap
+    let mut leaders: BTreeSet<usize> = targets.clone();
+    leaders.insert(0);
+    let mut expected = None;
+    for addr in &code {
+      if expected != Some(*addr) {
+        leaders.insert(*addr);
+      }
+      let instr = parse(*addr);
+      let next = addr + instr.size();
+      if instr.opcode.jumps() || instr.opcode == Opcode::Halt {
+        leaders.insert(next);
+      }
+      expected = Some(next);
+    }
+
+    let mut blocks = BTreeMap::new();
+    let mut functions = BTreeSet::new();
+    functions.insert(0);
+
+    for start in leaders.iter().filter(|x| code.contains(x)) {
+      let mut block = Block {
+        start: *start,
+        instructions: Vec::new(),
+        edges: Vec::new(),
+        indirect: false,
+        halts: false,
+        relative_base: Vec::new(),
+      };
+
+      let mut addr = *start;
+      let mut constants = BTreeSet::new();
+      loop {
+        let instr = parse(addr);
+        let next = addr + instr.size();
+
+        if let Some(x) = stored_constant(&instr) {
+          constants.insert(x);
+        }
+        if instr.opcode == Opcode::AdjustBase {
+          block.relative_base.push((addr, instr.params[0].clone()));
+        }
+
+        let opcode = instr.opcode;
+        block.instructions.push((addr, instr));
+
+        if opcode == Opcode::Halt {
+          block.halts = true;
+          break;
+        }
+
+        if opcode.jumps() {
+          let instr = &block.instructions.last().unwrap().1;
+          let (target, falls_through) = jump_targets(instr);
+          block.indirect = !matches!(instr.params[1], Param::Immediate(_));
+
+          match target {
+            Some(target) if !falls_through && constants.contains(&next) => {
+              functions.insert(target);
+              block.edges.push(Edge { to: target, kind: EdgeKind::Call });
+              if code.contains(&next) {
+                block.edges.push(Edge { to: next, kind: EdgeKind::ReturnSite });
+              }
+            },
+            Some(target) => block.edges.push(Edge { to: target, kind: EdgeKind::Jump }),
+            None => (),
+          }
+
+          if falls_through && code.contains(&next) {
+            block.edges.push(Edge { to: next, kind: EdgeKind::FallThrough });
+          }
+          break;
+        }
+
+        if leaders.contains(&next) || !code.contains(&next) {
+          if code.contains(&next) {
+            block.edges.push(Edge { to: next, kind: EdgeKind::FallThrough });
+          }
+          break;
+        }
+        addr = next;
+      }
+
+      blocks.insert(*start, block);
+    }
+
+    Cfg { blocks, functions }
+  }
+
+  // the block an instruction is in
+  pub fn block_at(&self, addr: usize) -> Option<&Block<W>> {
+    let (_, block) = self.blocks.range(..=addr).next_back()?;
+    Some(block).filter(|x| addr < x.end())
+  }
+
+  // the start of every block that can be reached from the one starting at
+  // from, including from itself
+  pub fn reachable(&self, from: usize) -> BTreeSet<usize> {
+    let mut seen = BTreeSet::new();
+    let mut todo = vec![from];
+
+    while let Some(addr) = todo.pop() {
+      let block = match self.blocks.get(&addr) {
+        Some(x) if seen.insert(addr) => x,
+        _ => continue,
+      };
+      todo.extend(block.edges.iter().map(|x| x.to));
+    }
+    seen
+  }
+
+  // graphviz, one box per block, e.g. dot -Tsvg cfg.dot > cfg.svg
+  pub fn to_dot(&self) -> String {
+    let mut out = String::new();
+    writeln!(out, "digraph cfg {{").unwrap();
+    writeln!(out, "  node [shape=box fontname=\"monospace\"];").unwrap();
+
+    for block in self.blocks.values() {
+      let mut text = format!("{}:\\l", label(block.start));
+      for (addr, instr) in &block.instructions {
+        write!(text, "{:>6}: {}\\l", addr, instr).unwrap();
+      }
+
+      let style = if self.functions.contains(&block.start) {
+        " style=bold"
+      } else if block.halts || block.indirect {
+        " style=rounded"
+      } else {
+        ""
+      };
+      writeln!(out, "  {} [label=\"{}\"{}];", label(block.start), text.replace('"', "\\\""), style).unwrap();
+
+      for edge in &block.edges {
+        let attrs = match edge.kind {
+          EdgeKind::FallThrough => "",
+          EdgeKind::Jump => " [color=blue]",
+          EdgeKind::Call => " [label=\"call\" style=bold]",
+          EdgeKind::ReturnSite => " [label=\"return\" style=dashed]",
+        };
+        writeln!(out, "  {} -> {}{};", label(block.start), label(edge.to), attrs).unwrap();
+      }
+    }
+
+    out.push_str("}\n");
+    out
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  use crate::fixtures::COMPARATOR;
+
+  fn edges(cfg: &Cfg<i64>, start: usize) -> Vec<(usize, EdgeKind)> {
+    cfg.blocks[&start].edges.iter().map(|x| (x.to, x.kind)).collect()
+  }
+
+  #[test]
+  fn test_blocks() {
+    let cfg = Cfg::build(&COMPARATOR);
+    assert_eq!(cfg.blocks.keys().copied().collect::<Vec<_>>(), [0, 9, 16, 22, 31, 36, 46]);
+    assert_eq!(cfg.functions.iter().copied().collect::<Vec<_>>(), [0]);
+
+    assert_eq!(edges(&cfg, 0), [(22, EdgeKind::Jump), (9, EdgeKind::FallThrough)]);
+    assert_eq!(edges(&cfg, 9), [(31, EdgeKind::Jump), (16, EdgeKind::FallThrough)]);
+    // jf #0 always jumps, so it never falls through into the data after it
+    assert_eq!(edges(&cfg, 16), [(36, EdgeKind::Jump)]);
+    assert!(cfg.blocks[&46].halts);
+
+    assert_eq!(cfg.block_at(4).map(|x| x.start), Some(0));
+    assert_eq!(cfg.block_at(19).map(|x| x.start), None);
+    assert_eq!(cfg.blocks[&0].end(), 9);
+
+    assert_eq!(cfg.reachable(0).len(), 7);
+    assert_eq!(cfg.reachable(36).into_iter().collect::<Vec<_>>(), [36, 46]);
+  }
+
+  #[test]
+  fn test_calls() {
+    // call a function at 10 that outputs 7 and returns via rel[+0]
+    let program: Vec<i64> = vec![
+      109, 20, // arb #20
+      21101, 9, 0, 0, // add #9 #0 -> rel[+0]
+      1105, 1, 10, // jt #1 #L10
+      99, // hlt
+      104, 7, // out #7
+      2105, 1, 0, // jt #1 rel[+0]
+    ];
+    let cfg = Cfg::build(&program);
+
+    assert_eq!(cfg.functions.iter().copied().collect::<Vec<_>>(), [0, 10]);
+    assert_eq!(edges(&cfg, 0), [(10, EdgeKind::Call), (9, EdgeKind::ReturnSite)]);
+    assert_eq!(cfg.blocks[&0].relative_base, [(0, Param::Immediate(20))]);
+    assert!(cfg.blocks[&10].indirect);
+    assert!(cfg.blocks[&10].edges.is_empty());
+
+    let dot = cfg.to_dot();
+    assert!(dot.starts_with("digraph cfg {\n"));
+    assert!(dot.contains("  L0 -> L10 [label=\"call\" style=bold];\n"));
+    assert!(dot.contains("  L0 -> L9 [label=\"return\" style=dashed];\n"));
+    assert!(dot.contains("  L10 [label=\"L10:\\l    10: out #7\\l    12: jt #1 rel[+0]\\l\" style=bold];\n"));
+  }
+}
@@ -0,0 +1,169 @@
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+
+use crate::instruction::{decode, Opcode};
+use crate::vm::{Snapshot, State, VM};
+use crate::word::Word;
+
+// why the debugger handed control back
+#[derive(Debug, Clone, PartialEq)]
+pub enum Stop<W: Word> {
+  Stepped,
+  Breakpoint(usize),
+  OpcodeBreak(Opcode),
+  Watch { addr: usize, old: W, new: W },
+  State(State<W>),
+}
+
+pub struct Debugger<W: Word = i64> {
+  pub vm: VM<W>,
+  pub breakpoints: BTreeSet<usize>,
+  pub opcode_breaks: HashSet<Opcode>,
+  // watched address -> value last time we looked
+  pub watches: BTreeMap<usize, W>,
+  pub output: Vec<W>,
+}
+
+impl<W: Word> Debugger<W> {
+  pub fn new(vm: VM<W>) -> Debugger<W> {
+    Debugger {
+      vm,
+      breakpoints: BTreeSet::new(),
+      opcode_breaks: HashSet::new(),
+      watches: BTreeMap::new(),
+      output: Vec::new(),
+    }
+  }
+
+  pub fn watch(&mut self, addr: usize) {
+    let value = self.vm.peek(addr);
+    self.watches.insert(addr, value);
+  }
+
+  // go back to a saved vm. watches pick up the values it has, so the
+  // jump itself isn't reported as a change
+  pub fn restore(&mut self, snapshot: &Snapshot<W>) {
+    self.vm.restore(snapshot);
+    for (addr, value) in self.watches.iter_mut() {
+      *value = self.vm.peek(*addr);
+    }
+  }
+
+  fn check_watches(&mut self) -> Option<Stop<W>> {
+    for (addr, old) in self.watches.iter_mut() {
+      let new = self.vm.peek(*addr);
+      if new != *old {
+        let stop = Stop::Watch { addr: *addr, old: old.clone(), new: new.clone() };
+        *old = new;
+        return Some(stop);
+      }
+    }
+    None
+  }
+
+  fn check_breaks(&self) -> Option<Stop<W>> {
+    let ip = self.vm.instruction_ptr();
+    if self.breakpoints.contains(&ip) {
+      return Some(Stop::Breakpoint(ip));
+    }
+
+    match decode(&self.vm.peek(ip)) {
+      Ok((opcode, _)) if self.opcode_breaks.contains(&opcode) => Some(Stop::OpcodeBreak(opcode)),
+      _ => None,
+    }
+  }
+
+  // run up to limit instructions, or forever if there's no limit, stopping
+  // early for breakpoints, watchpoints, input, halting and errors. we always
+  // run at least one instruction so continuing off a breakpoint works
+  pub fn run(&mut self, limit: Option<usize>) -> Stop<W> {
+    let mut count = 0;
+
+    loop {
+      match self.vm.step_instruction() {
+        Some(State::Output(x)) => self.output.push(x),
+        Some(state) => return Stop::State(state),
+        None => (),
+      }
+
+      if let Some(stop) = self.check_watches() {
+        return stop;
+      }
+
+      count += 1;
+      if Some(count) == limit {
+        return Stop::Stepped;
+      }
+
+      if let Some(stop) = self.check_breaks() {
+        return stop;
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  use crate::asm::assemble;
+
+  const COUNTER: &str = "
+          in -> [limit]
+    loop: add [count] #1 -> [count]
+          out [count]
+          lt [count] [limit] -> [flag]
+          jt [flag] #loop
+          hlt
+    count: .data 0
+    limit: .data 0
+    flag:  .data 0
+  ";
+
+  fn debugger() -> Debugger {
+    Debugger::new(VM::from_program(&assemble(COUNTER).unwrap()))
+  }
+
+  #[test]
+  fn test_step() {
+    let mut dbg = debugger();
+    assert_eq!(dbg.run(Some(1)), Stop::State(State::AwaitingInput));
+
+    dbg.vm.push_input(3);
+    assert_eq!(dbg.run(Some(3)), Stop::Stepped);
+    assert_eq!(dbg.vm.instruction_ptr(), 8);
+    assert_eq!(dbg.output, [1]);
+  }
+
+  #[test]
+  fn test_breakpoints() {
+    let mut dbg = debugger();
+    dbg.vm.push_input(3);
+    dbg.breakpoints.insert(2);
+
+    assert_eq!(dbg.run(None), Stop::Breakpoint(2));
+    assert_eq!(dbg.run(None), Stop::Breakpoint(2));
+    assert_eq!(dbg.output, [1]);
+
+    dbg.breakpoints.clear();
+    dbg.opcode_breaks.insert(Opcode::Halt);
+    assert_eq!(dbg.run(None), Stop::OpcodeBreak(Opcode::Halt));
+    assert_eq!(dbg.run(None), Stop::State(State::Halted));
+    assert_eq!(dbg.output, [1, 2, 3]);
+  }
+
+  #[test]
+  fn test_watches() {
+    let mut dbg = debugger();
+    dbg.vm.push_input(2);
+    dbg.watch(16);
+
+    assert_eq!(dbg.run(None), Stop::Watch { addr: 16, old: 0, new: 1 });
+    let snapshot = dbg.vm.snapshot();
+    assert_eq!(dbg.run(None), Stop::Watch { addr: 16, old: 1, new: 2 });
+
+    // after going back, the next change is from 1 again
+    dbg.restore(&snapshot);
+    assert_eq!(dbg.run(None), Stop::Watch { addr: 16, old: 1, new: 2 });
+    assert_eq!(dbg.run(None), Stop::State(State::Halted));
+  }
+}
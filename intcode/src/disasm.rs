@@ -0,0 +1,313 @@
+use std::collections::{BTreeSet, HashSet};
+use std::fmt;
+
+use crate::instruction::{Instruction, Opcode, Param};
+use crate::word::Word;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Item<W> {
+  Code(Instruction<W>),
+  Data(Vec<W>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Line<W> {
+  pub addr: usize,
+  pub item: Item<W>,
+}
+
+impl<W: Word> Line<W> {
+  // the raw words this line covers
+  pub fn words(&self) -> Vec<W> {
+    match &self.item {
+      Item::Code(instr) => instr.encode(),
+      Item::Data(words) => words.clone(),
+    }
+  }
+
+  pub fn size(&self) -> usize {
+    match &self.item {
+      Item::Code(instr) => instr.size(),
+      Item::Data(words) => words.len(),
+    }
+  }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Listing<W> {
+  pub lines: Vec<Line<W>>,
+  pub labels: BTreeSet<usize>,
+}
+
+impl<W: Word> Listing<W> {
+  pub fn line_at(&self, addr: usize) -> Option<&Line<W>> {
+    self.lines.iter().find(|x| x.addr <= addr && addr < x.addr + x.size())
+  }
+
+  // just the mnemonic part of a line, with jump targets swapped for labels
+  pub fn render(&self, line: &Line<W>) -> String {
+    match &line.item {
+      Item::Code(instr) if instr.opcode.jumps() => {
+        let target = match &instr.params[1] {
+          Param::Immediate(x) => x.to_usize().filter(|x| self.labels.contains(x)),
+          _ => None,
+        };
+
+        match target {
+          Some(addr) => format!("{} {} #{}", instr.opcode, instr.params[0], label(addr)),
+          None => instr.to_string(),
+        }
+      },
+      Item::Code(instr) => instr.to_string(),
+      Item::Data(words) => {
+        let words: Vec<String> = words.iter().map(|x| x.to_string()).collect();
+        format!(".data {}", words.join(", "))
+      },
+    }
+  }
+}
+
+pub fn label(addr: usize) -> String {
+  format!("L{}", addr)
+}
+
+//     0: 3,21                      in -> [21]
+// L22:
+//    22: 1002,21,125,20            mul [21] #125 -> [20]
+impl<W: Word> fmt::Display for Listing<W> {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    for line in &self.lines {
+      if self.labels.contains(&line.addr) {
+        writeln!(f, "{}:", label(line.addr))?;
+      }
+
+      let words: Vec<String> = line.words().iter().map(|x| x.to_string()).collect();
+      writeln!(f, "{:>6}: {:<26} {}", line.addr, words.join(","), self.render(line))?;
+    }
+    Ok(())
+  }
+}
+
+// where a jump instruction can go next: (target, whether it can fall through)
+pub(crate) fn jump_targets<W: Word>(instr: &Instruction<W>) -> (Option<usize>, bool) {
+  let taken = match (&instr.opcode, &instr.params[0]) {
+    (Opcode::JmpIfTrue, Param::Immediate(x)) => Some(!x.is_zero()),
+    (Opcode::JmpIfFalse, Param::Immediate(x)) => Some(x.is_zero()),
+    _ => None,
+  };
+
+  let target = match &instr.params[1] {
+    Param::Immediate(x) if taken != Some(false) => x.to_usize(),
+    _ => None,
+  };
+
+  (target, taken != Some(true))
+}
+
+// constants copied somewhere with an add or mul, e.g. add #46 #0 -> rel[+0].
+// a constant that points just past an unconditional jump is usually a
+// return address pushed before calling a function
+pub(crate) fn stored_constant<W: Word>(instr: &Instruction<W>) -> Option<usize> {
+  let identity = match instr.opcode {
+    Opcode::Add => W::zero(),
+    Opcode::Mul => W::one(),
+    _ => return None,
+  };
+
+  let (x, y) = match (&instr.params[0], &instr.params[1]) {
+    (Param::Immediate(x), Param::Immediate(y)) => (x, y),
+    _ => return None,
+  };
+
+  if *y == identity {
+    x.to_usize()
+  } else if *x == identity {
+    y.to_usize()
+  } else {
+    None
+  }
+}
+
+// walk every path through the program from address 0, returning the
+// addresses of instructions we think are code and the jump targets found
+pub(crate) fn find_code<W: Word>(program: &[W]) -> (BTreeSet<usize>, BTreeSet<usize>) {
+  let mut code = BTreeSet::new();
+  let mut targets = BTreeSet::new();
+  let mut constants = HashSet::new();
+  let mut dead_ends = Vec::new();
+  let mut todo = vec![0];
+
+  loop {
+    while let Some(addr) = todo.pop() {
+      if addr >= program.len() || code.contains(&addr) {
+        continue;
+      }
+
+      let instr = match Instruction::parse(&program[addr..]) {
+        Some(Ok(x)) => x,
+        _ => continue,
+      };
+      code.insert(addr);
+
+      let next = addr + instr.size();
+      match instr.opcode {
+        Opcode::Halt => (),
+        Opcode::JmpIfTrue | Opcode::JmpIfFalse => {
+          let (target, falls_through) = jump_targets(&instr);
+          if let Some(target) = target {
+            targets.insert(target);
+            todo.push(target);
+          }
+
+          if falls_through {
+            todo.push(next);
+          } else {
+            dead_ends.push(next);
+          }
+        },
+        _ => {
+          if let Some(x) = stored_constant(&instr) {
+            constants.insert(x);
+          }
+          todo.push(next);
+        },
+      }
+    }
+
+    // pick up anything after a call that looks like it gets returned to,
+    // and go around again until nothing new turns up
+    let returns: Vec<usize> = dead_ends
+      .iter()
+      .filter(|x| constants.contains(x) && !code.contains(x))
+      .copied()
+      .collect();
+
+    if returns.is_empty() {
+      break;
+    }
+
+    for addr in returns {
+      targets.insert(addr);
+      todo.push(addr);
+    }
+  }
+
+  targets.retain(|x| code.contains(x));
+  (code, targets)
+}
+
+fn data_line<W: Word>(program: &[W], start: usize, end: usize) -> Line<W> {
+  Line { addr: start, item: Item::Data(program[start..end].to_vec()) }
+}
+
+// disassemble a whole program, working out which parts are code by
+// following jumps from the start. everything else is listed as data
+pub fn disassemble<W: Word>(program: &[W]) -> Listing<W> {
+  let (code, labels) = find_code(program);
+  let mut lines = Vec::new();
+  let mut addr = 0;
+
+  while addr < program.len() {
+    if code.contains(&addr) {
+      if let Some(Ok(instr)) = Instruction::parse(&program[addr..]) {
+        let size = instr.size();
+        lines.push(Line { addr, item: Item::Code(instr) });
+        addr += size;
+        continue;
+      }
+    }
+
+    // run of data, up to the next bit of code or label, 8 words at a time
+    let mut end = addr + 1;
+    while end < program.len() && end - addr < 8 && !code.contains(&end) && !labels.contains(&end) {
+      end += 1;
+    }
+    lines.push(data_line(program, addr, end));
+    addr = end;
+  }
+
+  Listing { lines, labels }
+}
+
+// disassemble count lines starting at start, treating anything that decodes
+// as code. handy for looking at memory around the instruction pointer
+pub fn disassemble_linear<W: Word>(mem: &[W], start: usize, count: usize) -> Listing<W> {
+  let mut lines = Vec::new();
+  let mut addr = start;
+
+  while addr < mem.len() && lines.len() < count {
+    match Instruction::parse(&mem[addr..]) {
+      Some(Ok(instr)) => {
+        let size = instr.size();
+        lines.push(Line { addr, item: Item::Code(instr) });
+        addr += size;
+      },
+      _ => {
+        lines.push(data_line(mem, addr, addr + 1));
+        addr += 1;
+      },
+    }
+  }
+
+  Listing { lines, labels: BTreeSet::new() }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  use crate::fixtures::COMPARATOR;
+
+  #[test]
+  fn test_find_code() {
+    let (code, labels) = find_code(&COMPARATOR);
+    assert_eq!(
+      code.into_iter().collect::<Vec<_>>(),
+      [0, 2, 6, 9, 13, 16, 22, 26, 28, 31, 33, 36, 40, 42, 46],
+    );
+    assert_eq!(labels.into_iter().collect::<Vec<_>>(), [22, 31, 36, 46]);
+  }
+
+  #[test]
+  fn test_listing() {
+    let listing = disassemble(&COMPARATOR);
+    let text = listing.to_string();
+    let lines: Vec<&str> = text.lines().collect();
+
+    assert_eq!(lines[0], "     0: 3,21                       in -> [21]");
+    assert_eq!(lines[3], "     9: 107,8,21,20                lt #8 [21] -> [20]");
+    assert_eq!(lines[5], "    16: 1106,0,36                  jf #0 #L36");
+    assert_eq!(lines[6], "    19: 98,0,0                     .data 98, 0, 0");
+    assert_eq!(lines[7], "L22:");
+    assert_eq!(lines[lines.len() - 2], "L46:");
+    assert_eq!(lines[lines.len() - 1], "    46: 99                         hlt");
+  }
+
+  #[test]
+  fn test_return_addresses() {
+    // call a function at 10 that outputs 7 and returns via rel[+0]
+    let program: Vec<i64> = vec![
+      109, 20, // arb #20
+      21101, 9, 0, 0, // add #9 #0 -> rel[+0]
+      1105, 1, 10, // jt #1 #L10
+      99, // hlt
+      104, 7, // out #7
+      2105, 1, 0, // jt #1 rel[+0]
+    ];
+    let listing = disassemble(&program);
+
+    assert_eq!(listing.labels.iter().copied().collect::<Vec<_>>(), [9, 10]);
+    assert!(listing.lines.iter().all(|x| match x.item {
+      Item::Code(_) => true,
+      Item::Data(_) => false,
+    }));
+  }
+
+  #[test]
+  fn test_disassemble_linear() {
+    let listing = disassemble_linear(&COMPARATOR, 16, 3);
+    let rendered: Vec<String> = listing.lines.iter().map(|x| listing.render(x)).collect();
+    assert_eq!(rendered, ["jf #0 #36", ".data 98", ".data 0"]);
+    assert_eq!(listing.line_at(17).map(|x| x.addr), Some(16));
+  }
+}
@@ -0,0 +1,90 @@
+use std::error;
+use std::fmt;
+
+use crate::word::Word;
+
+// everything that can go wrong while running a program. ip is the address of
+// the instruction that failed and instruction is the raw word found there
+#[derive(Debug, Clone, PartialEq)]
+pub enum VmError<W: Word = i64> {
+  UnknownOpcode { ip: usize, instruction: W },
+  InvalidMode { ip: usize, instruction: W, mode: i64 },
+  // mode digits for params the instruction doesn't have
+  ExtraModeDigits { ip: usize, instruction: W },
+  NegativeAddress { ip: usize, instruction: W, address: W },
+  AddressOutOfRange { ip: usize, instruction: W, address: W },
+  ImmediateWriteTarget { ip: usize, instruction: W },
+  Overflow { ip: usize, instruction: W },
+  MemoryLimit { ip: usize, instruction: W, address: usize, limit: usize },
+  PcOutOfBounds { ip: usize },
+  NeedsInput { ip: usize },
+  // ran out of instruction budget. steps is how many instructions the vm
+  // has run in total
+  BudgetExhausted { ip: usize, steps: u64 },
+  // the same, but for running out of time
+  DeadlineExceeded { ip: usize, steps: u64 },
+}
+
+impl<W: Word> VmError<W> {
+  pub fn ip(&self) -> usize {
+    match self {
+      VmError::UnknownOpcode { ip, .. } => *ip,
+      VmError::InvalidMode { ip, .. } => *ip,
+      VmError::ExtraModeDigits { ip, .. } => *ip,
+      VmError::NegativeAddress { ip, .. } => *ip,
+      VmError::AddressOutOfRange { ip, .. } => *ip,
+      VmError::ImmediateWriteTarget { ip, .. } => *ip,
+      VmError::Overflow { ip, .. } => *ip,
+      VmError::MemoryLimit { ip, .. } => *ip,
+      VmError::PcOutOfBounds { ip } => *ip,
+      VmError::NeedsInput { ip } => *ip,
+      VmError::BudgetExhausted { ip, .. } => *ip,
+      VmError::DeadlineExceeded { ip, .. } => *ip,
+    }
+  }
+}
+
+impl<W: Word> fmt::Display for VmError<W> {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      VmError::UnknownOpcode { ip, instruction } => {
+        write!(f, "unknown opcode in {} at {}", instruction, ip)
+      },
+      VmError::InvalidMode { ip, instruction, mode } => {
+        write!(f, "invalid parameter mode {} in {} at {}", mode, instruction, ip)
+      },
+      VmError::ExtraModeDigits { ip, instruction } => {
+        write!(f, "mode digits past the last parameter in {} at {}", instruction, ip)
+      },
+      VmError::NegativeAddress { ip, instruction, address } => {
+        write!(f, "negative address {} used by {} at {}", address, instruction, ip)
+      },
+      VmError::AddressOutOfRange { ip, instruction, address } => {
+        write!(f, "address {} used by {} at {} is out of range", address, instruction, ip)
+      },
+      VmError::ImmediateWriteTarget { ip, instruction } => {
+        write!(f, "immediate mode write target in {} at {}", instruction, ip)
+      },
+      VmError::Overflow { ip, instruction } => {
+        write!(f, "overflow in {} at {}", instruction, ip)
+      },
+      VmError::MemoryLimit { ip, instruction, address, limit } => {
+        write!(f, "write to {} by {} at {} is past the memory limit of {}", address, instruction, ip, limit)
+      },
+      VmError::PcOutOfBounds { ip } => {
+        write!(f, "instruction pointer {} ran off the end of memory", ip)
+      },
+      VmError::NeedsInput { ip } => {
+        write!(f, "ran out of input at {}", ip)
+      },
+      VmError::BudgetExhausted { ip, steps } => {
+        write!(f, "gave up at {} after {} instructions", ip, steps)
+      },
+      VmError::DeadlineExceeded { ip, steps } => {
+        write!(f, "ran out of time at {} after {} instructions", ip, steps)
+      },
+    }
+  }
+}
+
+impl<W: Word> error::Error for VmError<W> {}
@@ -0,0 +1,18 @@
+// programs that tests in more than one module run
+
+// the day 5 comparator: 999 if input < 8, 1000 if == 8, 1001 if > 8
+pub const COMPARATOR: [i64; 47] = [
+  3, 21, 1008, 21, 8, 20, 1005, 20, 22, 107, 8, 21, 20, 1006, 20, 31, 1106, 0, 36, 98, 0, 0,
+  1002, 21, 125, 20, 4, 20, 1105, 1, 46, 104, 999, 1105, 1, 46, 1101, 1000, 1, 20, 4, 20, 1105,
+  1, 46, 98, 99,
+];
+
+// the day 7 feedback loop example, which gives 139629729 with phases 9, 8,
+// 7, 6 and 5 and a first input of 0
+pub const FEEDBACK: [i64; 29] = [
+  3, 26, 1001, 26, -4, 26, 3, 27, 1002, 27, 2, 27, 1, 27, 26, 27, 4, 27, 1001, 28, -1, 28,
+  1005, 28, 6, 99, 0, 0, 5,
+];
+
+// read two numbers, output their sum
+pub const ADDER: [i64; 14] = [3, 11, 3, 12, 1, 11, 12, 13, 4, 13, 99, 0, 0, 0];
@@ -0,0 +1,52 @@
+/*
+= gzip =
+gzipped programs are read with flate2. every member is inflated one after
+the other, and each one's crc and length are checked
+*/
+
+use std::error;
+use std::fmt;
+use std::io;
+use std::io::Read;
+
+use flate2::read::MultiGzDecoder;
+
+const MAGIC: &[u8] = &[0x1f, 0x8b];
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum GzipError {
+  NotGzip,
+  Truncated,
+  // what flate2 didn't like about it, e.g. a bad checksum
+  Corrupt(String),
+}
+
+impl fmt::Display for GzipError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      GzipError::NotGzip => write!(f, "not gzipped"),
+      GzipError::Truncated => write!(f, "gzipped data is truncated"),
+      GzipError::Corrupt(x) => write!(f, "corrupt gzipped data: {}", x),
+    }
+  }
+}
+
+impl error::Error for GzipError {}
+
+pub fn is_gzip(bytes: &[u8]) -> bool {
+  bytes.starts_with(MAGIC)
+}
+
+// gzip files can be stuck together, and unzip to everything stuck together
+pub fn gunzip(bytes: &[u8]) -> Result<Vec<u8>, GzipError> {
+  if !is_gzip(bytes) {
+    return Err(GzipError::NotGzip);
+  }
+
+  let mut out = Vec::new();
+  match MultiGzDecoder::new(bytes).read_to_end(&mut out) {
+    Ok(_) => Ok(out),
+    Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => Err(GzipError::Truncated),
+    Err(err) => Err(GzipError::Corrupt(err.to_string())),
+  }
+}
@@ -0,0 +1,340 @@
+use std::fmt;
+
+use crate::word::Word;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Opcode {
+  Add,
+  Mul,
+  In,
+  Out,
+  JmpIfTrue,
+  JmpIfFalse,
+  LessThan,
+  Equal,
+  AdjustBase,
+  Halt,
+}
+
+pub const OPCODES: [Opcode; 10] = [
+  Opcode::Add,
+  Opcode::Mul,
+  Opcode::In,
+  Opcode::Out,
+  Opcode::JmpIfTrue,
+  Opcode::JmpIfFalse,
+  Opcode::LessThan,
+  Opcode::Equal,
+  Opcode::AdjustBase,
+  Opcode::Halt,
+];
+
+impl Opcode {
+  pub fn from_code(code: i64) -> Option<Opcode> {
+    OPCODES.iter().copied().find(|op| op.code() == code)
+  }
+
+  pub fn from_mnemonic(mnemonic: &str) -> Option<Opcode> {
+    OPCODES.iter().copied().find(|op| op.mnemonic() == mnemonic)
+  }
+
+  pub fn code(self) -> i64 {
+    match self {
+      Opcode::Add => 1,
+      Opcode::Mul => 2,
+      Opcode::In => 3,
+      Opcode::Out => 4,
+      Opcode::JmpIfTrue => 5,
+      Opcode::JmpIfFalse => 6,
+      Opcode::LessThan => 7,
+      Opcode::Equal => 8,
+      Opcode::AdjustBase => 9,
+      Opcode::Halt => 99,
+    }
+  }
+
+  pub fn mnemonic(self) -> &'static str {
+    match self {
+      Opcode::Add => "add",
+      Opcode::Mul => "mul",
+      Opcode::In => "in",
+      Opcode::Out => "out",
+      Opcode::JmpIfTrue => "jt",
+      Opcode::JmpIfFalse => "jf",
+      Opcode::LessThan => "lt",
+      Opcode::Equal => "eq",
+      Opcode::AdjustBase => "arb",
+      Opcode::Halt => "hlt",
+    }
+  }
+
+  pub fn param_count(self) -> usize {
+    match self {
+      Opcode::Add | Opcode::Mul | Opcode::LessThan | Opcode::Equal => 3,
+      Opcode::JmpIfTrue | Opcode::JmpIfFalse => 2,
+      Opcode::In | Opcode::Out | Opcode::AdjustBase => 1,
+      Opcode::Halt => 0,
+    }
+  }
+
+  // whether the last param is an address that gets written to
+  pub fn writes(self) -> bool {
+    matches!(self, Opcode::Add | Opcode::Mul | Opcode::LessThan | Opcode::Equal | Opcode::In)
+  }
+
+  pub fn jumps(self) -> bool {
+    self == Opcode::JmpIfTrue || self == Opcode::JmpIfFalse
+  }
+}
+
+impl fmt::Display for Opcode {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{}", self.mnemonic())
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Mode {
+  Position,
+  Immediate,
+  Relative,
+}
+
+impl Mode {
+  pub fn from_digit(digit: i64) -> Option<Mode> {
+    match digit {
+      0 => Some(Mode::Position),
+      1 => Some(Mode::Immediate),
+      2 => Some(Mode::Relative),
+      _ => None,
+    }
+  }
+
+  pub fn digit(self) -> i64 {
+    match self {
+      Mode::Position => 0,
+      Mode::Immediate => 1,
+      Mode::Relative => 2,
+    }
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DecodeError {
+  UnknownOpcode,
+  InvalidMode(i64),
+  // a mode digit that isn't zero past the opcode's last param
+  ExtraModeDigits,
+  ImmediateWriteTarget,
+}
+
+// split an instruction word into its opcode and the modes of its params.
+// modes past the opcode's param count are left as position, and any digits
+// there that aren't zero are an error
+pub fn decode<W: Word>(instruction: &W) -> Result<(Opcode, [Mode; 3]), DecodeError> {
+  decode_with(instruction, false)
+}
+
+// decode the way the old day 9 vm did, for programs that rely on it. mode
+// digits wrap around % 3, so 3 is position and 4 immediate, digits past the
+// param count are ignored, and an immediate write target is treated as a
+// position. only unknown opcodes are errors
+pub fn decode_lenient<W: Word>(instruction: &W) -> Result<(Opcode, [Mode; 3]), DecodeError> {
+  decode_with(instruction, true)
+}
+
+fn decode_with<W: Word>(instruction: &W, lenient: bool) -> Result<(Opcode, [Mode; 3]), DecodeError> {
+  // anything that doesn't fit in an i64 can't be a real instruction
+  let raw = match instruction.to_i64() {
+    Some(x) if x >= 0 => x,
+    _ => return Err(DecodeError::UnknownOpcode),
+  };
+
+  let opcode = match Opcode::from_code(raw % 100) {
+    Some(x) => x,
+    None => return Err(DecodeError::UnknownOpcode),
+  };
+
+  let mut modes = [Mode::Position; 3];
+  let mut remaining = raw / 100;
+  for mode in modes.iter_mut().take(opcode.param_count()) {
+    let digit = if lenient { remaining % 10 % 3 } else { remaining % 10 };
+    *mode = match Mode::from_digit(digit) {
+      Some(x) => x,
+      None => return Err(DecodeError::InvalidMode(digit)),
+    };
+    remaining /= 10;
+  }
+
+  if !lenient && remaining != 0 {
+    return Err(DecodeError::ExtraModeDigits);
+  }
+
+  let target = &mut modes[opcode.param_count().max(1) - 1];
+  if opcode.writes() && *target == Mode::Immediate {
+    if !lenient {
+      return Err(DecodeError::ImmediateWriteTarget);
+    }
+    *target = Mode::Position;
+  }
+
+  Ok((opcode, modes))
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Param<W> {
+  Position(W),
+  Immediate(W),
+  Relative(W),
+}
+
+impl<W: Word> Param<W> {
+  pub fn new(mode: Mode, value: W) -> Param<W> {
+    match mode {
+      Mode::Position => Param::Position(value),
+      Mode::Immediate => Param::Immediate(value),
+      Mode::Relative => Param::Relative(value),
+    }
+  }
+
+  pub fn mode(&self) -> Mode {
+    match self {
+      Param::Position(_) => Mode::Position,
+      Param::Immediate(_) => Mode::Immediate,
+      Param::Relative(_) => Mode::Relative,
+    }
+  }
+
+  pub fn value(&self) -> &W {
+    match self {
+      Param::Position(x) | Param::Immediate(x) | Param::Relative(x) => x,
+    }
+  }
+}
+
+impl<W: Word> fmt::Display for Param<W> {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      Param::Position(x) => write!(f, "[{}]", x),
+      Param::Immediate(x) => write!(f, "#{}", x),
+      Param::Relative(x) if *x < W::zero() => write!(f, "rel[{}]", x),
+      Param::Relative(x) => write!(f, "rel[+{}]", x),
+    }
+  }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Instruction<W> {
+  pub opcode: Opcode,
+  pub params: Vec<Param<W>>,
+}
+
+impl<W: Word> Instruction<W> {
+  // decode the instruction at the start of mem. None if it runs off the end
+  pub fn parse(mem: &[W]) -> Option<Result<Instruction<W>, DecodeError>> {
+    let (opcode, modes) = match decode(mem.first()?) {
+      Ok(x) => x,
+      Err(err) => return Some(Err(err)),
+    };
+
+    let values = mem.get(1..=opcode.param_count())?;
+    let params = values
+      .iter()
+      .zip(modes.iter())
+      .map(|(value, mode)| Param::new(*mode, value.clone()))
+      .collect();
+
+    Some(Ok(Instruction { opcode, params }))
+  }
+
+  // how many words the instruction takes up
+  pub fn size(&self) -> usize {
+    self.params.len() + 1
+  }
+
+  // the instruction as words again
+  pub fn encode(&self) -> Vec<W> {
+    let mut code = 0;
+    let mut scale = 100;
+    for param in &self.params {
+      code += param.mode().digit() * scale;
+      scale *= 10;
+    }
+
+    let mut words = vec![W::from_i64(code + self.opcode.code())];
+    words.extend(self.params.iter().map(|x| x.value().clone()));
+    words
+  }
+}
+
+// add [100] #3 -> rel[+2]
+impl<W: Word> fmt::Display for Instruction<W> {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{}", self.opcode)?;
+    for (i, param) in self.params.iter().enumerate() {
+      if self.opcode.writes() && i == self.params.len() - 1 {
+        write!(f, " -> {}", param)?;
+      } else {
+        write!(f, " {}", param)?;
+      }
+    }
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use Param::*;
+
+  fn assert_parse(mem: &[i64], expected: &str) {
+    let instr = Instruction::parse(mem).unwrap().unwrap();
+    assert_eq!(instr.to_string(), expected);
+    assert_eq!(instr.encode(), &mem[..instr.size()]);
+  }
+
+  #[test]
+  fn test_decode() {
+    assert_eq!(decode(&1002i64), Ok((Opcode::Mul, [Mode::Position, Mode::Immediate, Mode::Position])));
+    assert_eq!(decode(&21101i64), Ok((Opcode::Add, [Mode::Immediate, Mode::Immediate, Mode::Relative])));
+    assert_eq!(decode(&99i64), Ok((Opcode::Halt, [Mode::Position; 3])));
+    assert_eq!(decode(&42i64), Err(DecodeError::UnknownOpcode));
+    assert_eq!(decode(&-1i64), Err(DecodeError::UnknownOpcode));
+    assert_eq!(decode(&301i64), Err(DecodeError::InvalidMode(3)));
+    assert_eq!(decode(&10001i64), Err(DecodeError::ImmediateWriteTarget));
+    // digits past the last param have to be zero too
+    assert_eq!(decode(&300001i64), Err(DecodeError::ExtraModeDigits));
+    assert_eq!(decode(&30099i64), Err(DecodeError::ExtraModeDigits));
+    assert_eq!(decode(&1104i64), Err(DecodeError::ExtraModeDigits));
+  }
+
+  #[test]
+  fn test_decode_lenient() {
+    assert_eq!(decode_lenient(&1002i64), decode(&1002i64));
+    assert_eq!(decode_lenient(&301i64), Ok((Opcode::Add, [Mode::Position; 3])));
+    assert_eq!(decode_lenient(&1504i64), Ok((Opcode::Out, [Mode::Relative, Mode::Position, Mode::Position])));
+    assert_eq!(decode_lenient(&11101i64), Ok((Opcode::Add, [Mode::Immediate, Mode::Immediate, Mode::Position])));
+    assert_eq!(decode_lenient(&403i64), Ok((Opcode::In, [Mode::Position; 3])));
+    assert_eq!(decode_lenient(&30099i64), Ok((Opcode::Halt, [Mode::Position; 3])));
+    assert_eq!(decode_lenient(&42i64), Err(DecodeError::UnknownOpcode));
+  }
+
+  #[test]
+  fn test_parse_instruction() {
+    assert_eq!(
+      Instruction::<i64>::parse(&[102, 1, 2, 3]),
+      Some(Ok(Instruction { opcode: Opcode::Mul, params: vec![Immediate(1), Position(2), Position(3)] })),
+    );
+    assert_eq!(Instruction::<i64>::parse(&[1, 2, 3]), None);
+    assert_eq!(Instruction::<i64>::parse(&[]), None);
+  }
+
+  #[test]
+  fn test_display() {
+    assert_parse(&[21001, 100, 3, 2], "add [100] #3 -> rel[+2]");
+    assert_parse(&[203, -1], "in -> rel[-1]");
+    assert_parse(&[1105, 1, 46], "jt #1 #46");
+    assert_parse(&[109, 19], "arb #19");
+    assert_parse(&[99, 1, 2], "hlt");
+  }
+}
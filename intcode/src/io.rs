@@ -0,0 +1,209 @@
+use std::collections::VecDeque;
+use std::fmt::Display;
+use std::io;
+use std::io::IsTerminal;
+use std::str::FromStr;
+use std::sync::mpsc::{Receiver, Sender, SyncSender};
+
+// somewhere for a vm to pull its input from. None means there's nothing
+// to give it right now, and the vm will stop and wait
+pub trait Input<W> {
+  fn read(&mut self) -> Option<W>;
+}
+
+// somewhere for a vm to send its output
+pub trait Output<W> {
+  fn write(&mut self, value: W);
+}
+
+impl<W, T: Input<W> + ?Sized> Input<W> for &mut T {
+  fn read(&mut self) -> Option<W> {
+    (**self).read()
+  }
+}
+
+impl<W, T: Output<W> + ?Sized> Output<W> for &mut T {
+  fn write(&mut self, value: W) {
+    (**self).write(value)
+  }
+}
+
+// reading from a slice eats it from the front
+impl<W: Clone> Input<W> for &[W] {
+  fn read(&mut self) -> Option<W> {
+    let (first, rest) = self.split_first()?;
+    *self = rest;
+    Some(first.clone())
+  }
+}
+
+impl<W> Input<W> for VecDeque<W> {
+  fn read(&mut self) -> Option<W> {
+    self.pop_front()
+  }
+}
+
+impl<W> Output<W> for VecDeque<W> {
+  fn write(&mut self, value: W) {
+    self.push_back(value);
+  }
+}
+
+impl<W> Output<W> for Vec<W> {
+  fn write(&mut self, value: W) {
+    self.push(value);
+  }
+}
+
+// wrappers to use closures as input or output
+pub struct InputFn<F>(pub F);
+pub struct OutputFn<F>(pub F);
+
+impl<W, F: FnMut() -> Option<W>> Input<W> for InputFn<F> {
+  fn read(&mut self) -> Option<W> {
+    (self.0)()
+  }
+}
+
+impl<W, F: FnMut(W)> Output<W> for OutputFn<F> {
+  fn write(&mut self, value: W) {
+    (self.0)(value)
+  }
+}
+
+// channels block until there's a value, and give up once the other end
+// has hung up
+impl<W> Input<W> for Receiver<W> {
+  fn read(&mut self) -> Option<W> {
+    self.recv().ok()
+  }
+}
+
+// if nobody is listening any more there's nothing useful to do with the
+// value, so it's dropped on the floor
+impl<W> Output<W> for Sender<W> {
+  fn write(&mut self, value: W) {
+    let _ = self.send(value);
+  }
+}
+
+impl<W> Output<W> for SyncSender<W> {
+  fn write(&mut self, value: W) {
+    let _ = self.send(value);
+  }
+}
+
+// ask whoever is at the terminal, one number per line. end of input, or
+// failing to read it, counts as running out. the prompt goes to stderr, and
+// only when someone's typing, so piped input gives clean output
+impl<W: FromStr> Input<W> for io::Stdin {
+  fn read(&mut self) -> Option<W> {
+    let interactive = self.is_terminal();
+
+    loop {
+      if interactive {
+        eprint!("input> ");
+      }
+
+      let mut inp = String::new();
+      match self.read_line(&mut inp) {
+        Ok(0) | Err(_) => return None,
+        Ok(_) => (),
+      }
+
+      match inp.trim().parse::<W>() {
+        Ok(x) => return Some(x),
+        Err(_) => eprintln!("not a number: {}", inp.trim()),
+      }
+    }
+  }
+}
+
+impl<W: Display> Output<W> for io::Stdout {
+  fn write(&mut self, value: W) {
+    println!("{}", value);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  use std::sync::mpsc;
+  use std::thread;
+
+  use crate::vm::VM;
+
+  // echo whatever comes in until we see a zero
+  const ECHO: [i64; 10] = [3, 9, 4, 9, 1005, 9, 0, 99, 0, 0];
+
+  #[test]
+  fn test_slice_input() {
+    let mut input: &[i64] = &[1, 2];
+    assert_eq!(input.read(), Some(1));
+    assert_eq!(input, [2]);
+    assert_eq!(input.read(), Some(2));
+    assert_eq!(input.read(), None);
+  }
+
+  #[test]
+  fn test_queues() {
+    let mut vm = VM::from_program(&ECHO);
+    let mut input = VecDeque::from(vec![4, 5]);
+    let mut output = VecDeque::new();
+
+    vm.run_with(&mut input, &mut output).unwrap();
+    assert!(!vm.is_halted());
+    assert_eq!(output, [4, 5]);
+
+    input.push_back(0);
+    vm.run_with(&mut input, &mut output).unwrap();
+    assert!(vm.is_halted());
+    assert_eq!(output, [4, 5, 0]);
+  }
+
+  #[test]
+  fn test_closures() {
+    let mut next = 3;
+    let mut seen = Vec::new();
+
+    let mut vm = VM::from_program(&ECHO);
+    vm.run_with(
+      &mut InputFn(|| {
+        next -= 1;
+        Some(next)
+      }),
+      &mut OutputFn(|x| seen.push(x * 10)),
+    ).unwrap();
+
+    assert!(vm.is_halted());
+    assert_eq!(seen, [20, 10, 0]);
+  }
+
+  #[test]
+  fn test_channels_between_vms() {
+    let (in_send, in_recv) = mpsc::channel();
+    let (mid_send, mid_recv) = mpsc::channel();
+    let (out_send, out_recv) = mpsc::channel();
+
+    // two echo vms chained together, each on its own thread
+    let first = thread::spawn(move || {
+      let mut vm = VM::from_program(&ECHO);
+      let (mut input, mut output) = (in_recv, mid_send);
+      vm.run_with(&mut input, &mut output).unwrap();
+    });
+    let second = thread::spawn(move || {
+      let mut vm = VM::from_program(&ECHO);
+      let (mut input, mut output) = (mid_recv, out_send);
+      vm.run_with(&mut input, &mut output).unwrap();
+    });
+
+    for x in &[8, 9, 0] {
+      in_send.send(*x).unwrap();
+    }
+
+    first.join().unwrap();
+    second.join().unwrap();
+    assert_eq!(out_recv.iter().collect::<Vec<_>>(), [8, 9, 0]);
+  }
+}
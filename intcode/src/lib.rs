@@ -0,0 +1,47 @@
+// shared intcode vm, used by every day that runs an intcode program
+
+mod asm;
+mod ascii;
+mod cfg;
+mod debugger;
+mod disasm;
+mod error;
+#[cfg(test)]
+mod fixtures;
+mod gzip;
+mod instruction;
+mod io;
+mod memory;
+mod network;
+mod patch;
+mod pipeline;
+mod profile;
+mod program;
+mod save;
+mod screen;
+mod search;
+mod trace;
+mod vm;
+mod word;
+
+pub use asm::{assemble, AsmError, AsmErrorKind};
+pub use ascii::{encode_line, render, run_ascii, AsciiInput, AsciiOutput};
+pub use cfg::{Block, Cfg, Edge, EdgeKind};
+pub use debugger::{Debugger, Stop};
+pub use disasm::{disassemble, disassemble_linear, Item, Line, Listing};
+pub use error::VmError;
+pub use gzip::{gunzip, GzipError};
+pub use instruction::{decode, decode_lenient, DecodeError, Instruction, Mode, Opcode, Param};
+pub use io::{Input, InputFn, Output, OutputFn};
+pub use memory::{Backend, LimitExceeded, Memory, PAGE_SIZE};
+pub use network::{NetEvent, Network, Packet, Routing};
+pub use patch::{PatchError, PatchSet, PatchSets};
+pub use pipeline::{run_pipeline, PipelineError, CHANNEL_CAPACITY};
+pub use profile::{profile, Profile};
+pub use program::{ParseError, ParseErrorKind, Program, ProgramError};
+pub use save::LoadError;
+pub use screen::{Palette, Screen, ScreenError};
+pub use search::{candidates, patched, run_candidate, search_best, search_first, Candidates, Dimension};
+pub use trace::{Event, JsonTracer, Tracer};
+pub use vm::{Snapshot, State, VM};
+pub use word::Word;
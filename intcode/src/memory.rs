@@ -0,0 +1,307 @@
+use std::collections::{BTreeMap, HashMap};
+use std::error;
+use std::fmt;
+use std::sync::Arc;
+
+use crate::word::Word;
+
+pub const PAGE_SIZE: usize = 1024;
+
+// how a vm's memory is stored. they all behave the same, reading anything
+// that's never been written gives zero, they just differ in what they cost
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+  // one flat vec, quickest for programs that keep to a small area
+  Dense,
+  // PAGE_SIZE cell pages, only allocated once something is written to
+  // them, so a far away write costs one page and not everything before it
+  Paged,
+  // a map entry per cell, for programs that scatter a few writes everywhere
+  Sparse,
+}
+
+// a write past the memory limit
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LimitExceeded {
+  pub addr: usize,
+  pub limit: usize,
+}
+
+impl fmt::Display for LimitExceeded {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "address {} is past the memory limit of {}", self.addr, self.limit)
+  }
+}
+
+impl error::Error for LimitExceeded {}
+
+// everything is behind an Arc, so cloning doesn't copy anything until one
+// side writes. paged memory only copies the page that was written to
+#[derive(Debug, Clone)]
+enum Store<W> {
+  Dense(Arc<Vec<W>>),
+  // page number -> page
+  Paged(BTreeMap<usize, Arc<Vec<W>>>),
+  Sparse(Arc<HashMap<usize, W>>),
+}
+
+// vm memory. cloning is cheap, so forking a vm halfway through a program is
+// too. with a limit set, writing at or past it is an error rather than
+// allocating however much it takes
+#[derive(Debug, Clone)]
+pub struct Memory<W> {
+  store: Store<W>,
+  len: usize,
+  limit: Option<usize>,
+}
+
+impl<W: Word> Memory<W> {
+  pub fn new(program: &[W]) -> Memory<W> {
+    Memory::with_backend(Backend::Paged, program)
+  }
+
+  pub fn with_backend(backend: Backend, program: &[W]) -> Memory<W> {
+    let store = match backend {
+      Backend::Dense => Store::Dense(Arc::new(program.to_vec())),
+      Backend::Paged => {
+        let pages = program.chunks(PAGE_SIZE).map(|chunk| {
+          let mut page = chunk.to_vec();
+          page.resize(PAGE_SIZE, W::zero());
+          Arc::new(page)
+        });
+        Store::Paged(pages.enumerate().collect())
+      },
+      Backend::Sparse => {
+        let cells = program.iter().cloned().enumerate().filter(|(_, x)| !x.is_zero());
+        Store::Sparse(Arc::new(cells.collect()))
+      },
+    };
+
+    Memory { store, len: program.len(), limit: None }
+  }
+
+  pub fn backend(&self) -> Backend {
+    match self.store {
+      Store::Dense(_) => Backend::Dense,
+      Store::Paged(_) => Backend::Paged,
+      Store::Sparse(_) => Backend::Sparse,
+    }
+  }
+
+  pub fn limit(&self) -> Option<usize> {
+    self.limit
+  }
+
+  // anything already past the limit stays readable, it just can't be
+  // written to any more
+  pub fn set_limit(&mut self, limit: Option<usize>) {
+    self.limit = limit;
+  }
+
+  // one past the highest address that's been loaded or written
+  pub fn len(&self) -> usize {
+    self.len
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.len == 0
+  }
+
+  // anything that's never been written is zero
+  pub fn read(&self, addr: usize) -> W {
+    let cell = match &self.store {
+      Store::Dense(cells) => cells.get(addr),
+      Store::Paged(pages) => pages.get(&(addr / PAGE_SIZE)).map(|page| &page[addr % PAGE_SIZE]),
+      Store::Sparse(cells) => cells.get(&addr),
+    };
+    cell.cloned().unwrap_or_else(W::zero)
+  }
+
+  // with no limit set, usize::MAX is still out of reach, so len can't
+  // overflow
+  pub fn write(&mut self, addr: usize, value: W) -> Result<(), LimitExceeded> {
+    let limit = self.limit.unwrap_or(usize::MAX);
+    if addr >= limit {
+      return Err(LimitExceeded { addr, limit });
+    }
+
+    match &mut self.store {
+      Store::Dense(cells) => {
+        let cells = Arc::make_mut(cells);
+        if addr >= cells.len() {
+          cells.resize(addr + 1, W::zero());
+        }
+        cells[addr] = value;
+      },
+      Store::Paged(pages) => {
+        let page = pages.entry(addr / PAGE_SIZE).or_insert_with(|| Arc::new(vec![W::zero(); PAGE_SIZE]));
+        Arc::make_mut(page)[addr % PAGE_SIZE] = value;
+      },
+      Store::Sparse(cells) => {
+        let cells = Arc::make_mut(cells);
+        if value.is_zero() {
+          cells.remove(&addr);
+        } else {
+          cells.insert(addr, value);
+        }
+      },
+    }
+
+    self.len = self.len.max(addr + 1);
+    Ok(())
+  }
+
+  // cells start..end as one contiguous run, zeros past the end
+  pub fn slice(&self, start: usize, end: usize) -> Vec<W> {
+    (start..end).map(|addr| self.read(addr)).collect()
+  }
+
+  // all of memory as one vec. only for memory that's known to be small,
+  // one far away write makes this huge. see runs for everything else
+  pub fn to_vec(&self) -> Vec<W> {
+    self.slice(0, self.len)
+  }
+
+  // every non-zero cell, lowest address first
+  fn cells(&self) -> Vec<(usize, W)> {
+    let non_zero = |(addr, x): (usize, &W)| Some((addr, x.clone())).filter(|_| !x.is_zero());
+
+    match &self.store {
+      Store::Dense(cells) => cells.iter().enumerate().filter_map(non_zero).collect(),
+      Store::Paged(pages) => pages
+        .iter()
+        .flat_map(|(n, page)| page.iter().enumerate().map(move |(i, x)| (n * PAGE_SIZE + i, x)))
+        .filter_map(non_zero)
+        .collect(),
+      Store::Sparse(cells) => {
+        let mut cells: Vec<(usize, W)> = cells.iter().map(|(addr, x)| (*addr, x.clone())).collect();
+        cells.sort_by_key(|(addr, _)| *addr);
+        cells
+      },
+    }
+  }
+
+  // the non-zero parts of memory as (start, cells) runs, lowest first.
+  // everything else up to len is zero, so that's all there is to it
+  pub fn runs(&self) -> Vec<(usize, Vec<W>)> {
+    let mut runs: Vec<(usize, Vec<W>)> = Vec::new();
+    for (addr, x) in self.cells() {
+      match runs.last_mut() {
+        Some((start, run)) if *start + run.len() == addr => run.push(x),
+        _ => runs.push((addr, vec![x])),
+      }
+    }
+    runs
+  }
+
+  // the other way round from runs and len
+  pub fn from_runs(backend: Backend, len: usize, runs: &[(usize, Vec<W>)]) -> Memory<W> {
+    let mut mem = Memory::with_backend(backend, &[]);
+    for (start, run) in runs {
+      for (i, x) in run.iter().enumerate() {
+        // there's no limit yet, so this can't fail
+        mem.write(start + i, x.clone()).unwrap();
+      }
+    }
+    mem.len = mem.len.max(len);
+    mem
+  }
+
+  // how many chunks of storage this shares with other, for seeing what a
+  // fork has copied. dense and sparse memory are a single chunk
+  pub fn shared_pages(&self, other: &Memory<W>) -> usize {
+    match (&self.store, &other.store) {
+      (Store::Dense(x), Store::Dense(y)) => Arc::ptr_eq(x, y) as usize,
+      (Store::Paged(x), Store::Paged(y)) => x
+        .iter()
+        .filter(|(n, page)| y.get(n).is_some_and(|other| Arc::ptr_eq(page, other)))
+        .count(),
+      (Store::Sparse(x), Store::Sparse(y)) => Arc::ptr_eq(x, y) as usize,
+      _ => 0,
+    }
+  }
+}
+
+impl<W: Word> PartialEq for Memory<W> {
+  fn eq(&self, other: &Memory<W>) -> bool {
+    self.len == other.len && self.runs() == other.runs()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const BACKENDS: [Backend; 3] = [Backend::Dense, Backend::Paged, Backend::Sparse];
+
+  #[test]
+  fn test_read_write() {
+    for backend in &BACKENDS {
+      let mut mem = Memory::<i64>::with_backend(*backend, &[1, 2, 3]);
+      assert_eq!(mem.len(), 3);
+      assert_eq!(mem.read(5000), 0);
+
+      mem.write(5000, 7).unwrap();
+      assert_eq!(mem.len(), 5001);
+      assert_eq!(mem.read(5000), 7);
+      assert_eq!(mem.read(4999), 0);
+      assert_eq!(mem.slice(1, 4), [2, 3, 0]);
+
+      mem.write(1, 0).unwrap();
+      assert_eq!(mem.slice(0, 3), [1, 0, 3]);
+      assert_eq!(mem, Memory::with_backend(Backend::Dense, &mem.to_vec()));
+    }
+  }
+
+  #[test]
+  fn test_limit() {
+    for backend in &BACKENDS {
+      let mut mem = Memory::<i64>::with_backend(*backend, &[1, 2, 3]);
+      mem.set_limit(Some(100));
+
+      assert_eq!(mem.write(99, 1), Ok(()));
+      assert_eq!(mem.write(1_000_000_000, 1), Err(LimitExceeded { addr: 1_000_000_000, limit: 100 }));
+      mem.set_limit(None);
+      assert_eq!(mem.write(usize::MAX, 1), Err(LimitExceeded { addr: usize::MAX, limit: usize::MAX }));
+      assert_eq!(mem.len(), 100);
+    }
+  }
+
+  #[test]
+  fn test_far_writes() {
+    // neither of these should go anywhere near allocating 2^50 cells
+    for backend in &[Backend::Paged, Backend::Sparse] {
+      let mut mem = Memory::<i64>::with_backend(*backend, &[1, 2, 3]);
+      mem.write(1 << 50, 5).unwrap();
+      assert_eq!(mem.read(1 << 50), 5);
+      assert_eq!(mem.len(), (1 << 50) + 1);
+
+      assert_eq!(mem.runs(), [(0, vec![1, 2, 3]), (1 << 50, vec![5])]);
+      assert_eq!(mem, Memory::from_runs(Backend::Sparse, mem.len(), &mem.runs()));
+      assert_ne!(mem, Memory::from_runs(Backend::Sparse, mem.len() + 1, &mem.runs()));
+    }
+  }
+
+  #[test]
+  fn test_copy_on_write() {
+    let program: Vec<i64> = (0..3000).collect();
+    let mut mem = Memory::new(&program);
+    let fork = mem.clone();
+    assert_eq!(mem.shared_pages(&fork), 3);
+
+    // only the page that was written to gets copied
+    mem.write(1500, -1).unwrap();
+    assert_eq!(mem.shared_pages(&fork), 2);
+    assert_eq!(mem.read(1500), -1);
+    assert_eq!(fork.read(1500), 1500);
+    assert_eq!(fork.to_vec(), program);
+
+    for backend in &[Backend::Dense, Backend::Sparse] {
+      let mut mem = Memory::with_backend(*backend, &program);
+      let fork = mem.clone();
+      mem.write(1500, -1).unwrap();
+      assert_eq!(mem.shared_pages(&fork), 0);
+      assert_eq!(fork.read(1500), 1500);
+    }
+  }
+}
@@ -0,0 +1,326 @@
+use std::collections::VecDeque;
+
+use crate::error::VmError;
+use crate::vm::{State, VM};
+use crate::word::Word;
+
+// where the nodes' outputs go
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Routing {
+  // every output goes straight to the next node's input, and the last
+  // node's back round to the first. the day 7 feedback loop
+  Ring,
+  // outputs are grouped into (dest, x, y) packets, and x and y are queued
+  // up on node dest
+  Packets,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Packet<W> {
+  pub from: usize,
+  pub dest: W,
+  pub x: W,
+  pub y: W,
+}
+
+// why the network handed control back
+#[derive(Debug, Clone, PartialEq)]
+pub enum NetEvent<W: Word> {
+  // a packet for an address with no node on it
+  Unrouted(Packet<W>),
+  // the nat was sent a packet
+  NatReceived(Packet<W>),
+  // the network went idle, so the nat sent the last packet it got to node 0
+  NatSent(Packet<W>),
+  // nothing's being sent and every node is just reading the idle input
+  Idle,
+  // every node that hasn't halted is waiting on input nobody will send
+  Deadlock,
+  // every node has halted, or faulted
+  Halted,
+  // a node failed. it's left out of the network from then on
+  Faulted { node: usize, error: VmError<W> },
+}
+
+// a bunch of vms whose outputs are fed to each other's inputs. each call to
+// run goes round the nodes in turn, letting each one run until it blocks on
+// input, and stops as soon as something needs the caller's attention
+pub struct Network<W: Word = i64> {
+  nodes: Vec<VM<W>>,
+  routing: Routing,
+  // outputs that aren't a whole packet yet, per node
+  partial: Vec<Vec<W>>,
+  last_output: Vec<Option<W>>,
+  faulted: Vec<bool>,
+  // what a node reads when it has nothing queued, rather than blocking
+  idle_input: Option<W>,
+  nat: Option<usize>,
+  nat_packet: Option<Packet<W>>,
+  events: VecDeque<NetEvent<W>>,
+}
+
+impl<W: Word> Network<W> {
+  pub fn new(nodes: Vec<VM<W>>, routing: Routing) -> Network<W> {
+    let count = nodes.len();
+    Network {
+      nodes,
+      routing,
+      partial: vec![Vec::new(); count],
+      last_output: vec![None; count],
+      faulted: vec![false; count],
+      idle_input: None,
+      nat: None,
+      nat_packet: None,
+      events: VecDeque::new(),
+    }
+  }
+
+  // count copies of vm, each given its address as its first input, sending
+  // packets to each other. a node with nothing to read gets -1
+  pub fn with_addresses(vm: &VM<W>, count: usize) -> Network<W> {
+    let nodes = (0..count)
+      .map(|addr| {
+        let mut node = vm.clone();
+        node.push_input(W::from_i64(addr as i64));
+        node
+      })
+      .collect();
+
+    let mut network = Network::new(nodes, Routing::Packets);
+    network.set_idle_input(Some(W::from_i64(-1)));
+    network
+  }
+
+  pub fn set_idle_input(&mut self, value: Option<W>) {
+    self.idle_input = value;
+  }
+
+  // packets sent to addr go to a nat, which sends the last one it got on to
+  // node 0 whenever the network goes idle
+  pub fn set_nat(&mut self, addr: Option<usize>) {
+    self.nat = addr;
+  }
+
+  pub fn len(&self) -> usize {
+    self.nodes.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.nodes.is_empty()
+  }
+
+  pub fn node(&self, n: usize) -> &VM<W> {
+    &self.nodes[n]
+  }
+
+  pub fn node_mut(&mut self, n: usize) -> &mut VM<W> {
+    &mut self.nodes[n]
+  }
+
+  pub fn last_output(&self, n: usize) -> Option<&W> {
+    self.last_output[n].as_ref()
+  }
+
+  fn deliver(&mut self, packet: Packet<W>) {
+    let dest = packet.dest.to_usize();
+
+    if dest.is_some() && dest == self.nat {
+      self.nat_packet = Some(packet.clone());
+      self.events.push_back(NetEvent::NatReceived(packet));
+      return;
+    }
+
+    match dest.filter(|x| *x < self.nodes.len()) {
+      Some(dest) => self.nodes[dest].push_inputs(&[packet.x, packet.y]),
+      None => self.events.push_back(NetEvent::Unrouted(packet)),
+    }
+  }
+
+  fn route(&mut self, from: usize, value: W) {
+    self.last_output[from] = Some(value.clone());
+
+    match self.routing {
+      Routing::Ring => {
+        let next = (from + 1) % self.nodes.len();
+        self.nodes[next].push_input(value);
+      },
+      Routing::Packets => {
+        self.partial[from].push(value);
+        if self.partial[from].len() == 3 {
+          let mut values = std::mem::take(&mut self.partial[from]).into_iter();
+          let (dest, x, y) = (values.next().unwrap(), values.next().unwrap(), values.next().unwrap());
+          self.deliver(Packet { from, dest, x, y });
+        }
+      },
+    }
+  }
+
+  fn is_done(&self, n: usize) -> bool {
+    self.nodes[n].is_halted() || self.faulted[n]
+  }
+
+  // run a node until it blocks, returning whether it did anything: read
+  // real input or sent something
+  fn run_node(&mut self, n: usize) -> bool {
+    if self.is_done(n) {
+      return false;
+    }
+
+    let queued = self.nodes[n].pending_input().len();
+    let mut sent = false;
+    let mut fed_idle = false;
+
+    loop {
+      match self.nodes[n].step() {
+        State::Output(x) => {
+          sent = true;
+          self.route(n, x);
+        },
+        State::AwaitingInput => match &self.idle_input {
+          Some(x) if !fed_idle => {
+            fed_idle = true;
+            let x = x.clone();
+            self.nodes[n].push_input(x);
+          },
+          _ => break,
+        },
+        State::Halted => break,
+        State::Faulted(error) => {
+          self.faulted[n] = true;
+          self.events.push_back(NetEvent::Faulted { node: n, error });
+          break;
+        },
+      }
+    }
+
+    // the idle input is only ever given once the queue's empty, and if
+    // nothing was sent nothing else can have been added, so whatever's left
+    // over is real input that wasn't read
+    let left = if fed_idle { 0 } else { self.nodes[n].pending_input().len() };
+    sent || left < queued
+  }
+
+  pub fn run(&mut self) -> NetEvent<W> {
+    loop {
+      if let Some(event) = self.events.pop_front() {
+        return event;
+      }
+
+      if (0..self.nodes.len()).all(|n| self.is_done(n)) {
+        return NetEvent::Halted;
+      }
+
+      let mut busy = false;
+      for n in 0..self.nodes.len() {
+        busy |= self.run_node(n);
+      }
+
+      if busy || !self.events.is_empty() {
+        continue;
+      }
+
+      if let Some(packet) = self.nat_packet.clone() {
+        if let Some(node) = self.nodes.first_mut() {
+          node.push_inputs(&[packet.x.clone(), packet.y.clone()]);
+          return NetEvent::NatSent(packet);
+        }
+      }
+
+      if self.idle_input.is_some() {
+        return NetEvent::Idle;
+      }
+      return NetEvent::Deadlock;
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  use crate::asm::assemble;
+  use crate::fixtures::FEEDBACK;
+
+  // reads its address, then passes every (x, y) it gets on to address + 1
+  // with y bumped by one. node 0 starts things off with (5, 0)
+  const FORWARDER: &str = "
+          in -> [addr]
+          jt [addr] #loop
+          out #1
+          out #5
+          out #0
+    loop: in -> [x]
+          eq [x] #-1 -> [flag]
+          jt [flag] #loop
+          in -> [y]
+          add [addr] #1 -> [dest]
+          add [y] #1 -> [y]
+          out [dest]
+          out [x]
+          out [y]
+          jt #1 #loop
+    addr: .data 0
+    x:    .data 0
+    y:    .data 0
+    dest: .data 0
+    flag: .data 0
+  ";
+
+  fn forwarders(count: usize) -> Network {
+    Network::with_addresses(&VM::from_program(&assemble(FORWARDER).unwrap()), count)
+  }
+
+  #[test]
+  fn test_ring() {
+    let nodes = [9, 8, 7, 6, 5]
+      .iter()
+      .map(|phase| {
+        let mut vm = VM::<i64>::from_program(&FEEDBACK);
+        vm.push_input(*phase);
+        vm
+      })
+      .collect();
+
+    let mut network = Network::new(nodes, Routing::Ring);
+    network.node_mut(0).push_input(0);
+    assert_eq!(network.run(), NetEvent::Halted);
+    assert_eq!(network.last_output(4), Some(&139629729));
+  }
+
+  #[test]
+  fn test_packets() {
+    let mut network = forwarders(3);
+    assert_eq!(network.run(), NetEvent::Unrouted(Packet { from: 2, dest: 3, x: 5, y: 2 }));
+    assert_eq!(network.run(), NetEvent::Idle);
+  }
+
+  #[test]
+  fn test_nat() {
+    let mut network = forwarders(2);
+    network.set_nat(Some(2));
+
+    assert_eq!(network.run(), NetEvent::NatReceived(Packet { from: 1, dest: 2, x: 5, y: 1 }));
+    assert_eq!(network.run(), NetEvent::NatSent(Packet { from: 1, dest: 2, x: 5, y: 1 }));
+    assert_eq!(network.run(), NetEvent::NatReceived(Packet { from: 1, dest: 2, x: 5, y: 3 }));
+    assert_eq!(network.run(), NetEvent::NatSent(Packet { from: 1, dest: 2, x: 5, y: 3 }));
+  }
+
+  #[test]
+  fn test_deadlock_and_faults() {
+    // two nodes that both want input before they'll say anything
+    let mut network = Network::new(vec![VM::<i64>::from_program(&[3, 0, 99]); 2], Routing::Ring);
+    assert_eq!(network.run(), NetEvent::Deadlock);
+
+    // a halted node with input it'll never read isn't doing anything
+    let mut network = Network::new(vec![VM::<i64>::from_program(&[99]), VM::from_program(&[104, 7, 3, 0, 99])], Routing::Ring);
+    assert_eq!(network.run(), NetEvent::Deadlock);
+
+    // a node that faults is reported once and then left alone
+    let mut network = Network::new(vec![VM::<i64>::from_program(&[104, 1, 99]), VM::from_program(&[42])], Routing::Ring);
+    assert_eq!(
+      network.run(),
+      NetEvent::Faulted { node: 1, error: VmError::UnknownOpcode { ip: 0, instruction: 42 } },
+    );
+    assert_eq!(network.run(), NetEvent::Halted);
+  }
+}
@@ -0,0 +1,205 @@
+/*
+= patch files =
+named sets of values to write over a program before it runs, e.g. day 2's
+1202 program alarm. blank lines and anything after a # are ignored
+
+    # restore the state just before the fire
+    [alarm]
+    1 = 12
+    2 = 2
+*/
+
+use std::error;
+use std::fmt;
+
+use crate::memory::LimitExceeded;
+use crate::vm::VM;
+use crate::word::Word;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PatchError {
+  // the address isn't part of the program image, which is usually a typo
+  // rather than a deliberate write to scratch memory
+  OutOfImage { addr: usize, len: usize },
+  Limit(LimitExceeded),
+  // line numbers start at 1
+  Syntax { line: usize, message: String },
+  DuplicateSet(String),
+  UnknownSet(String),
+}
+
+impl fmt::Display for PatchError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      PatchError::OutOfImage { addr, len } => {
+        write!(f, "address {} is outside the {} word program", addr, len)
+      },
+      PatchError::Limit(x) => write!(f, "{}", x),
+      PatchError::Syntax { line, message } => write!(f, "line {}: {}", line, message),
+      PatchError::DuplicateSet(x) => write!(f, "patch set {} is defined twice", x),
+      PatchError::UnknownSet(x) => write!(f, "no patch set called {}", x),
+    }
+  }
+}
+
+impl error::Error for PatchError {}
+
+impl From<LimitExceeded> for PatchError {
+  fn from(err: LimitExceeded) -> PatchError {
+    PatchError::Limit(err)
+  }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PatchSet<W> {
+  pub name: String,
+  pub patches: Vec<(usize, W)>,
+}
+
+impl<W: Word> PatchSet<W> {
+  pub fn new(name: &str, patches: &[(usize, W)]) -> PatchSet<W> {
+    PatchSet { name: name.to_string(), patches: patches.to_vec() }
+  }
+
+  // every address is checked against the image and the memory limit
+  // before anything is written, so a bad set leaves vm as it was
+  pub fn apply(&self, vm: &mut VM<W>) -> Result<(), PatchError> {
+    let len = vm.image_len();
+    let limit = vm.memory().limit();
+    for (addr, _) in &self.patches {
+      if *addr >= len {
+        return Err(PatchError::OutOfImage { addr: *addr, len });
+      }
+      if let Some(limit) = limit.filter(|x| addr >= x) {
+        return Err(PatchError::Limit(LimitExceeded { addr: *addr, limit }));
+      }
+    }
+
+    for (addr, value) in &self.patches {
+      vm.patch(*addr, value.clone())?;
+    }
+    Ok(())
+  }
+}
+
+// every set in a patch file, in the order they appear
+#[derive(Debug, Clone, PartialEq)]
+pub struct PatchSets<W> {
+  pub sets: Vec<PatchSet<W>>,
+}
+
+impl<W: Word> PatchSets<W> {
+  pub fn parse(text: &str) -> Result<PatchSets<W>, PatchError> {
+    let mut sets: Vec<PatchSet<W>> = Vec::new();
+
+    for (n, line) in text.lines().enumerate() {
+      let syntax = |message: &str| PatchError::Syntax { line: n + 1, message: message.to_string() };
+
+      let line = line.split('#').next().unwrap_or("").trim();
+      if line.is_empty() {
+        continue;
+      }
+
+      if line.starts_with('[') {
+        let name = line
+          .strip_prefix('[')
+          .and_then(|x| x.strip_suffix(']'))
+          .map(|x| x.trim())
+          .filter(|x| !x.is_empty())
+          .ok_or_else(|| syntax("expected [name]"))?;
+
+        if sets.iter().any(|x| x.name == name) {
+          return Err(PatchError::DuplicateSet(name.to_string()));
+        }
+        sets.push(PatchSet::new(name, &[]));
+        continue;
+      }
+
+      let mut parts = line.splitn(2, '=').map(|x| x.trim());
+      let (addr, value) = match (parts.next(), parts.next()) {
+        (Some(addr), Some(value)) => (addr, value),
+        _ => return Err(syntax("expected addr = value")),
+      };
+      let addr = addr.parse().map_err(|_| syntax(&format!("bad address {}", addr)))?;
+      let value = value.parse().map_err(|_| syntax(&format!("bad value {}", value)))?;
+
+      match sets.last_mut() {
+        Some(set) => set.patches.push((addr, value)),
+        None => return Err(syntax("patch before the first [name]")),
+      }
+    }
+
+    Ok(PatchSets { sets })
+  }
+
+  pub fn get(&self, name: &str) -> Option<&PatchSet<W>> {
+    self.sets.iter().find(|x| x.name == name)
+  }
+
+  pub fn apply(&self, name: &str, vm: &mut VM<W>) -> Result<(), PatchError> {
+    match self.get(name) {
+      Some(set) => set.apply(vm),
+      None => Err(PatchError::UnknownSet(name.to_string())),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const PATCHES: &str = "
+    # day 2
+    [alarm]
+    1 = 12
+    2 = 2   # verb
+
+    [ zeros ]
+    1=0
+  ";
+
+  #[test]
+  fn test_parse() {
+    let sets = PatchSets::<i64>::parse(PATCHES).unwrap();
+    assert_eq!(sets.sets, [PatchSet::new("alarm", &[(1, 12), (2, 2)]), PatchSet::new("zeros", &[(1, 0)])]);
+    assert_eq!(sets.get("zeros").map(|x| x.patches.len()), Some(1));
+    assert_eq!(sets.get("nope"), None);
+
+    let syntax = |line, message: &str| Err(PatchError::Syntax { line, message: message.to_string() });
+    assert_eq!(PatchSets::<i64>::parse("1 = 2"), syntax(1, "patch before the first [name]"));
+    assert_eq!(PatchSets::<i64>::parse("[a]\n\n1 2"), syntax(3, "expected addr = value"));
+    assert_eq!(PatchSets::<i64>::parse("[a]\n-1 = 2"), syntax(2, "bad address -1"));
+    assert_eq!(PatchSets::<i64>::parse("[a]\n1 = x"), syntax(2, "bad value x"));
+    assert_eq!(PatchSets::<i64>::parse("[a"), syntax(1, "expected [name]"));
+    assert_eq!(PatchSets::<i64>::parse("[a]\n[a]"), Err(PatchError::DuplicateSet("a".to_string())));
+  }
+
+  #[test]
+  fn test_apply() {
+    let sets = PatchSets::<i64>::parse(PATCHES).unwrap();
+    let mut vm = VM::from_program(&[1, 0, 0, 0, 99]);
+
+    sets.apply("alarm", &mut vm).unwrap();
+    assert_eq!(vm.memory().to_vec(), [1, 12, 2, 0, 99]);
+
+    assert_eq!(sets.apply("nope", &mut vm), Err(PatchError::UnknownSet("nope".to_string())));
+
+    // nothing gets written if any of it is out of bounds
+    let set = PatchSet::new("bad", &[(0, 2), (5, 1)]);
+    assert_eq!(set.apply(&mut vm), Err(PatchError::OutOfImage { addr: 5, len: 5 }));
+    assert_eq!(vm.memory().to_vec(), [1, 12, 2, 0, 99]);
+
+    vm.set_memory_limit(Some(2));
+    assert_eq!(vm.patch(3, 1), Err(PatchError::Limit(LimitExceeded { addr: 3, limit: 2 })));
+    let set = PatchSet::new("limited", &[(1, 7), (3, 1)]);
+    assert_eq!(set.apply(&mut vm), Err(PatchError::Limit(LimitExceeded { addr: 3, limit: 2 })));
+    assert_eq!(vm.memory().to_vec(), [1, 12, 2, 0, 99]);
+
+    // scratch memory the program writes to isn't part of the image
+    let mut vm = VM::<i64>::from_program(&[1101, 2, 3, 10, 99]);
+    vm.run(&[]).unwrap();
+    assert_eq!(vm.memory().len(), 11);
+    assert_eq!(vm.patch(10, 1), Err(PatchError::OutOfImage { addr: 10, len: 5 }));
+    assert_eq!(vm.clone().patch(7, 1), Err(PatchError::OutOfImage { addr: 7, len: 5 }));
+  }
+}
@@ -0,0 +1,259 @@
+use std::error;
+use std::fmt;
+use std::sync::mpsc::{sync_channel, Receiver, RecvTimeoutError, SyncSender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use crate::error::VmError;
+use crate::io::{Input, OutputFn};
+use crate::vm::VM;
+use crate::word::Word;
+
+// how many values can be waiting between two stages before the one sending
+// has to wait for the one receiving to catch up
+pub const CHANNEL_CAPACITY: usize = 1024;
+
+// how often a stage waiting for input looks to see if everyone else is too
+const DEADLOCK_CHECK_INTERVAL: Duration = Duration::from_millis(10);
+
+// a stage of the pipeline that failed, numbered from 0
+#[derive(Debug, Clone, PartialEq)]
+pub struct PipelineError<W: Word> {
+  pub stage: usize,
+  pub error: VmError<W>,
+}
+
+impl<W: Word> fmt::Display for PipelineError<W> {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "stage {}: {}", self.stage, self.error)
+  }
+}
+
+impl<W: Word> error::Error for PipelineError<W> {}
+
+#[derive(Default)]
+struct Tally {
+  // values sent that haven't been received yet
+  in_flight: usize,
+  // stages waiting for input, or that have stopped
+  waiting: usize,
+}
+
+// what the stages share for spotting a deadlock. with feedback every stage
+// holds a sender for the next one, so no channel ever closes and a loop of
+// stages all waiting on each other would wait forever.
+//
+// both counts live behind one lock, so they're always seen together. a value
+// is counted as in flight before it's sent, and stops being counted in the
+// same breath as its receiver stops waiting, so nothing in flight and every
+// stage waiting really does mean nothing can ever happen again
+struct Counts {
+  stages: usize,
+  tally: Mutex<Tally>,
+}
+
+impl Counts {
+  fn update(&self, f: impl FnOnce(&mut Tally)) {
+    f(&mut self.tally.lock().unwrap());
+  }
+
+  fn deadlocked(&self) -> bool {
+    let tally = self.tally.lock().unwrap();
+    tally.in_flight == 0 && tally.waiting == self.stages
+  }
+}
+
+// a stage's channel, which gives up once every stage is waiting and there's
+// nothing on its way to any of them
+struct StageInput<W> {
+  receiver: Receiver<W>,
+  counts: Arc<Counts>,
+}
+
+impl<W> Input<W> for StageInput<W> {
+  fn read(&mut self) -> Option<W> {
+    if let Ok(x) = self.receiver.try_recv() {
+      self.counts.update(|tally| tally.in_flight -= 1);
+      return Some(x);
+    }
+
+    self.counts.update(|tally| tally.waiting += 1);
+    loop {
+      match self.receiver.recv_timeout(DEADLOCK_CHECK_INTERVAL) {
+        Ok(x) => {
+          self.counts.update(|tally| {
+            tally.in_flight -= 1;
+            tally.waiting -= 1;
+          });
+          return Some(x);
+        },
+        // still counted as waiting, it's about to stop for good
+        Err(RecvTimeoutError::Disconnected) => return None,
+        Err(RecvTimeoutError::Timeout) if self.counts.deadlocked() => return None,
+        Err(RecvTimeoutError::Timeout) => (),
+      }
+    }
+  }
+}
+
+// run a chain of vms, each on its own thread, with each one's output going
+// to the next one's input. the first vm gets input, and with feedback the
+// last one's output goes back round to the first as well. returns everything
+// the last vm output, once every vm has halted.
+//
+// a vm stops when it halts, or when it wants input and whatever was feeding
+// it has stopped, or when every vm is waiting for input. anything that stops
+// without halting is an error, the same as VM::run running out of input. if
+// more than one stage fails, the first one that didn't just run out of input
+// is reported, as that's most likely what starved the rest
+pub fn run_pipeline<W>(vms: Vec<VM<W>>, input: &[W], feedback: bool) -> Result<Vec<W>, PipelineError<W>>
+where
+  W: Word + Send + Sync + 'static,
+{
+  let count = vms.len();
+  let counts = Arc::new(Counts { stages: count, tally: Mutex::new(Tally::default()) });
+
+  // channel n feeds vm n
+  let (senders, receivers): (Vec<SyncSender<W>>, Vec<Receiver<W>>) =
+    (0..count).map(|_| sync_channel(CHANNEL_CAPACITY)).unzip();
+
+  let mut handles = Vec::new();
+  for (n, (mut vm, receiver)) in vms.into_iter().zip(receivers).enumerate() {
+    let last = n + 1 == count;
+    let sender = match (last, feedback) {
+      (false, _) => Some(senders[n + 1].clone()),
+      (true, true) => Some(senders[0].clone()),
+      (true, false) => None,
+    };
+
+    if n == 0 {
+      vm.push_inputs(input);
+    }
+
+    let counts = counts.clone();
+    handles.push(thread::spawn(move || {
+      let mut input = StageInput { receiver, counts: counts.clone() };
+      let mut outputs = Vec::new();
+      let mut output = OutputFn(|x: W| {
+        if let Some(sender) = &sender {
+          // if the next stage has already stopped there's nobody to tell
+          counts.update(|tally| tally.in_flight += 1);
+          if sender.send(x.clone()).is_err() {
+            counts.update(|tally| tally.in_flight -= 1);
+          }
+        }
+        if last {
+          outputs.push(x);
+        }
+      });
+
+      let result = match vm.run_with(&mut input, &mut output) {
+        Ok(()) if !vm.is_halted() => Err(VmError::NeedsInput { ip: vm.instruction_ptr() }),
+        x => x,
+      };
+
+      // a stage that's stopped is as good as waiting forever, unless it
+      // stopped because of waiting, in which case it's been counted already
+      if !matches!(result, Err(VmError::NeedsInput { .. })) {
+        input.counts.update(|tally| tally.waiting += 1);
+      }
+      (result, outputs)
+    }));
+  }
+
+  // only the threads hold senders now, so a channel closes as soon as the
+  // stage feeding it finishes
+  drop(senders);
+
+  let mut errors = Vec::new();
+  let mut result = Vec::new();
+  for (stage, handle) in handles.into_iter().enumerate() {
+    let (status, outputs) = handle.join().expect("pipeline thread panicked!");
+    match status {
+      Ok(()) => result = outputs,
+      Err(error) => errors.push(PipelineError { stage, error }),
+    }
+  }
+
+  let starved = |x: &PipelineError<W>| matches!(x.error, VmError::NeedsInput { .. });
+  match errors.iter().position(|x| !starved(x)) {
+    Some(n) => Err(errors.swap_remove(n)),
+    None if !errors.is_empty() => Err(errors.swap_remove(0)),
+    None => Ok(result),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  use crate::fixtures::FEEDBACK;
+
+  // outputs whatever it reads plus one, until it reads a 0 which it passes
+  // on before halting
+  const ADD_ONE: [i64; 18] = [3, 17, 1006, 17, 14, 101, 1, 17, 17, 4, 17, 1105, 1, 0, 104, 0, 99, 0];
+
+  #[test]
+  fn test_chain() {
+    let vms = vec![VM::<i64>::from_program(&ADD_ONE); 4];
+    assert_eq!(run_pipeline(vms, &[1, 10, 0], false), Ok(vec![5, 14, 0]));
+
+    // a broken stage is reported, rather than the one after it starving
+    let mut vms = vec![VM::<i64>::from_program(&ADD_ONE); 4];
+    vms[2] = VM::from_program(&[42]);
+    assert_eq!(
+      run_pipeline(vms, &[1, 10, 0], false),
+      Err(PipelineError { stage: 2, error: VmError::UnknownOpcode { ip: 0, instruction: 42 } }),
+    );
+  }
+
+  #[test]
+  fn test_passing_along() {
+    // out (in + 1), hlt
+    let program = [3, 9, 101, 1, 9, 9, 4, 9, 99, 0];
+    let vms = vec![VM::<i64>::from_program(&program); 5];
+    assert_eq!(run_pipeline(vms, &[10], false), Ok(vec![15]));
+
+    // the first stage doesn't get enough input
+    let vms = vec![VM::<i64>::from_program(&program); 3];
+    assert_eq!(
+      run_pipeline(vms, &[], false),
+      Err(PipelineError { stage: 0, error: VmError::NeedsInput { ip: 0 } }),
+    );
+  }
+
+  #[test]
+  fn test_feedback() {
+    let vms = [9, 8, 7, 6, 5]
+      .iter()
+      .map(|phase| {
+        let mut vm = VM::<i64>::from_program(&FEEDBACK);
+        vm.push_input(*phase);
+        vm
+      })
+      .collect();
+
+    let outputs = run_pipeline(vms, &[0], true).unwrap();
+    assert_eq!(outputs.last(), Some(&139629729));
+  }
+
+  #[test]
+  fn test_feedback_failures() {
+    // both stages want input before they'll output anything, so they end up
+    // waiting on each other
+    let vms = vec![VM::<i64>::from_program(&[3, 0, 3, 0, 99]); 2];
+    assert_eq!(
+      run_pipeline(vms, &[], true),
+      Err(PipelineError { stage: 0, error: VmError::NeedsInput { ip: 0 } }),
+    );
+
+    // the broken stage is what gets reported, not the ones it starved
+    let mut vms = vec![VM::<i64>::from_program(&ADD_ONE); 3];
+    vms[2] = VM::from_program(&[42]);
+    assert_eq!(
+      run_pipeline(vms, &[1, 10, 0], true),
+      Err(PipelineError { stage: 2, error: VmError::UnknownOpcode { ip: 0, instruction: 42 } }),
+    );
+  }
+}
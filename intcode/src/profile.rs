@@ -0,0 +1,249 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+
+use crate::disasm::{disassemble, label, Item};
+use crate::error::VmError;
+use crate::instruction::{Instruction, Opcode, OPCODES};
+use crate::trace::{Event, Tracer};
+use crate::vm::VM;
+use crate::word::Word;
+
+// counts of everything a run did, by address and by opcode. it's a tracer,
+// so it can be set on a vm like any other, or see profile for a one-off run
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Profile {
+  pub steps: u64,
+  // how many times the instruction at each address ran
+  pub executions: BTreeMap<usize, u64>,
+  pub opcodes: HashMap<Opcode, u64>,
+  // reads and writes of each memory cell, not counting fetching instructions
+  pub reads: BTreeMap<usize, u64>,
+  pub writes: BTreeMap<usize, u64>,
+}
+
+impl<W: Word> Tracer<W> for Profile {
+  fn after(&mut self, event: &Event<W>) {
+    self.steps += 1;
+    *self.executions.entry(event.ip).or_insert(0) += 1;
+    *self.opcodes.entry(event.instruction.opcode).or_insert(0) += 1;
+
+    for (addr, _) in &event.reads {
+      *self.reads.entry(*addr).or_insert(0) += 1;
+    }
+    for (addr, _) in &event.writes {
+      *self.writes.entry(*addr).or_insert(0) += 1;
+    }
+  }
+}
+
+// the top count of counts, biggest first and lowest address first for ties
+fn top(counts: &BTreeMap<usize, u64>, count: usize) -> Vec<(usize, u64)> {
+  let mut sorted: Vec<(usize, u64)> = counts.iter().map(|(addr, x)| (*addr, *x)).collect();
+  sorted.sort_by_key(|(addr, x)| (std::cmp::Reverse(*x), *addr));
+  sorted.truncate(count);
+  sorted
+}
+
+impl Profile {
+  pub fn new() -> Profile {
+    Profile::default()
+  }
+
+  pub fn count(&self, addr: usize) -> u64 {
+    self.executions.get(&addr).copied().unwrap_or(0)
+  }
+
+  // addresses the disassembler thinks are code that never ran, e.g. error
+  // handling or the other half of a puzzle
+  pub fn untouched<W: Word>(&self, program: &[W]) -> Vec<usize> {
+    let listing = disassemble(program);
+    let code = listing.lines.iter().filter(|x| matches!(x.item, Item::Code(_)));
+    code.map(|x| x.addr).filter(|x| self.count(*x) == 0).collect()
+  }
+
+  // a summary of where the time went and what was never touched, with the
+  // top count addresses and cells in each list
+  pub fn report<W: Word>(&self, program: &[W], count: usize) -> String {
+    let mut out = String::new();
+    let percent = |x: u64| 100.0 * x as f64 / self.steps.max(1) as f64;
+
+    writeln!(out, "{} instructions", self.steps).unwrap();
+
+    writeln!(out, "\nby opcode:").unwrap();
+    let mut opcodes: Vec<(Opcode, u64)> = OPCODES.iter().filter_map(|x| Some((*x, *self.opcodes.get(x)?))).collect();
+    opcodes.sort_by_key(|(_, x)| std::cmp::Reverse(*x));
+    for (opcode, x) in opcodes {
+      writeln!(out, "  {:<4} {:>10} {:>6.1}%", opcode.mnemonic(), x, percent(x)).unwrap();
+    }
+
+    writeln!(out, "\nhottest addresses:").unwrap();
+    for (addr, x) in top(&self.executions, count) {
+      let instr = match program.get(addr..).and_then(Instruction::parse) {
+        Some(Ok(instr)) => instr.to_string(),
+        _ => "?".to_string(),
+      };
+      writeln!(out, "  {:>6}: {:>10} {:>6.1}%  {}", addr, x, percent(x), instr).unwrap();
+    }
+
+    for (name, counts) in &[("read", &self.reads), ("written", &self.writes)] {
+      writeln!(out, "\nmost {} cells:", name).unwrap();
+      for (addr, x) in top(counts, count) {
+        writeln!(out, "  {:>6}: {:>10}", addr, x).unwrap();
+      }
+    }
+
+    // runs of untouched code one after the other, skipping over any data
+    // in between, as the first and last address and how many there are
+    let listing = disassemble(program);
+    let mut runs: Vec<(usize, usize, usize)> = Vec::new();
+    let mut current = None;
+    for line in &listing.lines {
+      if let Item::Code(_) = line.item {
+        current = match (current, self.count(line.addr)) {
+          (None, 0) => Some((line.addr, line.addr, 1)),
+          (Some((first, _, n)), 0) => Some((first, line.addr, n + 1)),
+          (run, _) => {
+            runs.extend(run);
+            None
+          },
+        };
+      }
+    }
+    runs.extend(current);
+
+    let total: usize = runs.iter().map(|(_, _, n)| n).sum();
+    writeln!(out, "\nnever run: {} instructions", total).unwrap();
+    for (first, last, n) in runs {
+      writeln!(out, "  {:>6}-{:<6} {:>4} instructions", first, last, n).unwrap();
+    }
+    out
+  }
+
+  // the disassembly with how many times each instruction ran down the side.
+  // - is code that never ran
+  //
+  //            L4:
+  //          3      4: 1001,19,-1,19              add [19] #-1 -> [19]
+  //          -     14: 104,1                      out #1
+  pub fn annotate<W: Word>(&self, program: &[W]) -> String {
+    let listing = disassemble(program);
+    let mut out = String::new();
+
+    for line in &listing.lines {
+      if listing.labels.contains(&line.addr) {
+        writeln!(out, "{:>10} {}:", "", label(line.addr)).unwrap();
+      }
+
+      let count = match (&line.item, self.count(line.addr)) {
+        (Item::Code(_), 0) => "-".to_string(),
+        (Item::Code(_), x) => x.to_string(),
+        (Item::Data(_), _) => String::new(),
+      };
+      let words: Vec<String> = line.words().iter().map(|x| x.to_string()).collect();
+      writeln!(out, "{:>10} {:>6}: {:<26} {}", count, line.addr, words.join(","), listing.render(line)).unwrap();
+    }
+    out
+  }
+}
+
+// run vm with a profile watching, handing the profile back along with
+// whatever run would have. any tracer the vm already had is put back after
+pub fn profile<W: Word>(vm: &mut VM<W>, input: &[W]) -> (Result<Vec<W>, VmError<W>>, Profile) {
+  let profile = Arc::new(Mutex::new(Profile::new()));
+  let old = vm.clear_tracer();
+  vm.set_tracer(Box::new(profile.clone()));
+
+  let result = vm.run(input);
+
+  vm.clear_tracer();
+  if let Some(tracer) = old {
+    vm.set_tracer(tracer);
+  }
+
+  let profile = profile.lock().unwrap().clone();
+  (result, profile)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  use crate::asm::assemble;
+
+  // counts n down from 3, then outputs it. the out #1 is never reached
+  const COUNTDOWN: &str = "
+          add #3 #0 -> [n]
+    loop: add [n] #-1 -> [n]
+          jt [n] #loop
+          jf [n] #end
+          out #1
+    end:  out [n]
+          hlt
+    n:    .data 0
+  ";
+
+  fn countdown() -> (Vec<i64>, Profile) {
+    let program = assemble(COUNTDOWN).unwrap();
+    let mut vm = VM::from_program(&program);
+    let (result, profile) = profile(&mut vm, &[]);
+    assert_eq!(result, Ok(vec![0]));
+    (program, profile)
+  }
+
+  #[test]
+  fn test_counts() {
+    let (program, profile) = countdown();
+
+    assert_eq!(profile.steps, 10);
+    assert_eq!(profile.executions.iter().map(|(a, x)| (*a, *x)).collect::<Vec<_>>(), [
+      (0, 1),
+      (4, 3),
+      (8, 3),
+      (11, 1),
+      (16, 1),
+      (18, 1),
+    ]);
+    assert_eq!(profile.opcodes[&Opcode::Add], 4);
+    assert_eq!(profile.opcodes.get(&Opcode::In), None);
+    assert_eq!(profile.reads[&19], 8);
+    assert_eq!(profile.writes[&19], 4);
+    assert_eq!(profile.untouched(&program), [14]);
+  }
+
+  #[test]
+  fn test_report() {
+    let (program, profile) = countdown();
+    let report = profile.report(&program, 2);
+
+    assert!(report.starts_with("10 instructions\n\nby opcode:\n  add           4   40.0%\n  jt            3   30.0%\n"));
+    assert!(report.contains("hottest addresses:\n       4:          3   30.0%  add [19] #-1 -> [19]\n"));
+    assert!(report.contains("most read cells:\n      19:          8\n"));
+    assert!(report.ends_with("never run: 1 instructions\n      14-14        1 instructions\n"));
+
+    let annotated = profile.annotate(&program);
+    let lines: Vec<&str> = annotated.lines().collect();
+    assert_eq!(lines[0], "         1      0: 1101,3,0,19                add #3 #0 -> [19]");
+    assert_eq!(lines[1], "           L4:");
+    assert_eq!(lines[2], "         3      4: 1001,19,-1,19              add [19] #-1 -> [19]");
+    assert_eq!(lines[5], "         -     14: 104,1                      out #1");
+    assert_eq!(lines[lines.len() - 1], "               19: 0                          .data 0");
+  }
+
+  #[test]
+  fn test_keeps_tracer() {
+    let program = assemble(COUNTDOWN).unwrap();
+    let mut vm = VM::<i64>::from_program(&program);
+    let outer = Arc::new(Mutex::new(Profile::new()));
+    vm.set_tracer(Box::new(outer.clone()));
+
+    let (_, inner) = profile(&mut vm, &[]);
+    assert_eq!(inner.steps, 10);
+    assert_eq!(outer.lock().unwrap().steps, 0);
+
+    // the vm's own tracer is back in place afterwards
+    vm.restore(&VM::from_program(&program).snapshot());
+    vm.run(&[]).unwrap();
+    assert_eq!(outer.lock().unwrap().steps, 10);
+  }
+}
@@ -0,0 +1,244 @@
+/*
+= program files =
+comma separated words, the way puzzle inputs come. whitespace and newlines
+can go anywhere between words, # starts a comment that runs to the end of
+the line, and a trailing comma is fine
+
+    # day 2 example
+    1,9,10,3,
+    2,3,11,0,
+    99,
+    30,40,50
+
+a file can be gzipped, which is spotted by its magic number rather than its
+name, so that works through stdin too
+*/
+
+use std::error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::io::Read;
+use std::path::Path;
+
+use crate::gzip::{gunzip, is_gzip, GzipError};
+use crate::vm::VM;
+use crate::word::Word;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParseErrorKind {
+  // not a number, or one too big for the word type
+  BadNumber,
+  // two words with only whitespace between them
+  MissingComma,
+  // a comma at the start, or two in a row
+  MissingValue,
+}
+
+// line and col start at 1, col counts chars rather than bytes. token is
+// whatever was found where things went wrong
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+  pub line: usize,
+  pub col: usize,
+  pub token: String,
+  pub kind: ParseErrorKind,
+}
+
+impl fmt::Display for ParseError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "line {}, col {}: ", self.line, self.col)?;
+    match self.kind {
+      ParseErrorKind::BadNumber => write!(f, "bad number {}", self.token),
+      ParseErrorKind::MissingComma => write!(f, "missing comma before {}", self.token),
+      ParseErrorKind::MissingValue => write!(f, "missing value before {}", self.token),
+    }
+  }
+}
+
+impl error::Error for ParseError {}
+
+#[derive(Debug)]
+pub enum ProgramError {
+  Io(io::Error),
+  Gzip(GzipError),
+  NotText,
+  Parse(ParseError),
+}
+
+impl fmt::Display for ProgramError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      ProgramError::Io(x) => write!(f, "{}", x),
+      ProgramError::Gzip(x) => write!(f, "{}", x),
+      ProgramError::NotText => write!(f, "program isn't utf-8 text"),
+      ProgramError::Parse(x) => write!(f, "{}", x),
+    }
+  }
+}
+
+impl error::Error for ProgramError {}
+
+impl From<io::Error> for ProgramError {
+  fn from(err: io::Error) -> ProgramError {
+    ProgramError::Io(err)
+  }
+}
+
+impl From<GzipError> for ProgramError {
+  fn from(err: GzipError) -> ProgramError {
+    ProgramError::Gzip(err)
+  }
+}
+
+impl From<ParseError> for ProgramError {
+  fn from(err: ParseError) -> ProgramError {
+    ProgramError::Parse(err)
+  }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Program<W: Word = i64> {
+  pub words: Vec<W>,
+}
+
+impl<W: Word> Program<W> {
+  pub fn parse(text: &str) -> Result<Program<W>, ParseError> {
+    let mut words = Vec::new();
+    // true at the start and after a comma
+    let mut want_value = true;
+
+    for (n, line) in text.lines().enumerate() {
+      let line = line.split('#').next().unwrap_or("");
+      let mut chars = line.char_indices().enumerate().peekable();
+
+      while let Some((col, (start, c))) = chars.next() {
+        let error = |token: &str, kind| ParseError { line: n + 1, col: col + 1, token: token.to_string(), kind };
+
+        if c.is_whitespace() {
+          continue;
+        }
+
+        if c == ',' {
+          if want_value {
+            return Err(error(",", ParseErrorKind::MissingValue));
+          }
+          want_value = true;
+          continue;
+        }
+
+        let mut end = line.len();
+        while let Some((_, (x, c))) = chars.peek() {
+          if c.is_whitespace() || *c == ',' {
+            end = *x;
+            break;
+          }
+          chars.next();
+        }
+
+        let token = &line[start..end];
+        if !want_value {
+          return Err(error(token, ParseErrorKind::MissingComma));
+        }
+        words.push(token.parse().map_err(|_| error(token, ParseErrorKind::BadNumber))?);
+        want_value = false;
+      }
+    }
+
+    Ok(Program { words })
+  }
+
+  // text, or gzipped text
+  pub fn from_bytes(bytes: &[u8]) -> Result<Program<W>, ProgramError> {
+    let bytes = if is_gzip(bytes) { gunzip(bytes)? } else { bytes.to_vec() };
+    let text = String::from_utf8(bytes).map_err(|_| ProgramError::NotText)?;
+    Ok(Program::parse(&text)?)
+  }
+
+  pub fn from_reader<R: Read>(mut reader: R) -> Result<Program<W>, ProgramError> {
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes)?;
+    Program::from_bytes(&bytes)
+  }
+
+  // a path of - reads stdin
+  pub fn load<P: AsRef<Path>>(path: P) -> Result<Program<W>, ProgramError> {
+    let path = path.as_ref();
+    if path == Path::new("-") {
+      return Program::from_reader(io::stdin());
+    }
+    Program::from_bytes(&fs::read(path)?)
+  }
+
+  pub fn vm(&self) -> VM<W> {
+    VM::from_program(&self.words)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn hex(text: &str) -> Vec<u8> {
+    (0..text.len()).step_by(2).map(|i| u8::from_str_radix(&text[i..i + 2], 16).unwrap()).collect()
+  }
+
+  #[test]
+  fn test_parse() {
+    let text = "# day 2 example\n1,9,10,3,\r\n  2,3,11,0, # the important bit\n99,\n\n30 , 40,50,\n";
+    assert_eq!(Program::<i64>::parse(text).unwrap().words, [1, 9, 10, 3, 2, 3, 11, 0, 99, 30, 40, 50]);
+    assert_eq!(Program::<i64>::parse("").unwrap().words, [0i64; 0]);
+
+    let error = |line, col, token: &str, kind| Err(ParseError { line, col, token: token.to_string(), kind });
+    assert_eq!(Program::<i64>::parse("1,2,\n3,x4,5"), error(2, 3, "x4", ParseErrorKind::BadNumber));
+    assert_eq!(Program::<i64>::parse("1,2\n3"), error(2, 1, "3", ParseErrorKind::MissingComma));
+    assert_eq!(Program::<i64>::parse("1,,2"), error(1, 3, ",", ParseErrorKind::MissingValue));
+    assert_eq!(Program::<i64>::parse("  ,1"), error(1, 3, ",", ParseErrorKind::MissingValue));
+    assert_eq!(Program::<i64>::parse("é,99999999999999999999"), error(1, 1, "é", ParseErrorKind::BadNumber));
+    assert_eq!(Program::<i64>::parse("0,99999999999999999999"), error(1, 3, "99999999999999999999", ParseErrorKind::BadNumber));
+    assert_eq!(Program::<i128>::parse("99999999999999999999").unwrap().words, [99999999999999999999]);
+  }
+
+  #[test]
+  fn test_gzip() {
+    let expected = [1, 9, 10, 3, 2, 3, 11, 0, 99, 30, 40, 50];
+
+    // fixed huffman codes, with a file name in the header
+    let fixed = hex(concat!(
+      "1f8b08080000000002ff646179322e7478740033d4b1d43134d031d631026243431d031d4b4b1d63031d13031d53032e0091",
+      "f51f9f1e000000",
+    ));
+    assert_eq!(Program::<i64>::from_bytes(&fixed).unwrap().words, expected);
+
+    // not compressed at all
+    let stored = hex(concat!(
+      "1f8b08000000000000ff011e00e1ff312c392c31302c332c322c332c31312c302c39392c33302c34302c35300a91f51f9f1e",
+      "000000",
+    ));
+    assert_eq!(Program::<i64>::from_bytes(&stored).unwrap().words, expected);
+
+    // two members stuck together
+    let text = b"1,9,10,3,2,3,11,0,99,30,40,50\n";
+    assert_eq!(gunzip(&[fixed.clone(), stored].concat()), Ok([&text[..], &text[..]].concat()));
+
+    // dynamic huffman codes, for (0..120).map(|x| x * 37 % 1000)
+    let dynamic = hex(concat!(
+      "1f8b08000000000002ff1dd1b90d03500804d1dcb56cc0f981fe1bf3d839e249b3a61c4dc9dde5b5f26d4584a24f714f99c985a9",
+      "6c5455aa75b5afba5b7da117a7f79ec69237a699d17a69dbb5bbba68dd0bdd9d92b34432a4412a24475aa4460a2e0ee9212592",
+      "210d522139d2223552f0e6901e522219d220159223ad92b390db213da44432a4412a24475aa4460ad51cd2434a24431aa44272",
+      "a4456aa4d0d6213da44432a451724639a35c3512e59c72fd902897864439a35c3912e59c721deaa55c3c24ca19e57e43b0c4b2",
+      "c4b2c4b2c4b1c4b1c4fd97e00c8972ece0ec10ec10ec10ec90f1eb4b39cbcf17625ba929d4010000",
+    ));
+    let expected: Vec<i64> = (0..120).map(|x| x * 37 % 1000).collect();
+    assert_eq!(Program::<i64>::from_bytes(&dynamic).unwrap().words, expected);
+
+    let mut broken = fixed.clone();
+    let last = broken.len() - 5;
+    broken[last] ^= 1;
+    assert!(matches!(Program::<i64>::from_bytes(&broken), Err(ProgramError::Gzip(GzipError::Corrupt(_)))));
+    assert!(matches!(
+      Program::<i64>::from_bytes(&fixed[..30]),
+      Err(ProgramError::Gzip(GzipError::Truncated))
+    ));
+  }
+}
@@ -0,0 +1,487 @@
+/*
+= binary format =
+everything is a varint (7 bits at a time, low bits first, top bit set on all
+but the last byte) apart from the magic, the halted flag and words. a word
+is a varint length followed by that many bytes of little endian two's
+complement, so zero is a single 0 byte
+
+    "ICVM" version ip relative_base halted(0/1) backend limit
+    memory_len run_count (start run_len word...)... input_len word...
+
+backend is 0 for dense, 1 for paged and 2 for sparse, and limit is the
+memory limit plus one, or 0 for no limit. memory is only its non-zero runs,
+so a far away write doesn't make the save any bigger than it has to be
+
+= json format =
+    {"version":2,"ip":2,"relative_base":0,"halted":false,"backend":"paged","limit":null,
+     "memory_len":14,"memory":[[0,[3,11,...]],[20,[1]]],"input":[6]}
+
+version 1 is still read. it had no backend or limit, and memory was every
+cell from zero, i.e. memory_len word... and "memory":[3,11,...]
+*/
+
+use std::error;
+use std::fmt;
+
+use serde_json::{Map, Value};
+
+use crate::memory::{Backend, Memory};
+use crate::vm::Snapshot;
+use crate::word::Word;
+
+const VERSION: u64 = 2;
+const BACKENDS: [(Backend, &str); 3] = [(Backend::Dense, "dense"), (Backend::Paged, "paged"), (Backend::Sparse, "sparse")];
+const MAGIC: &[u8] = b"ICVM";
+
+// the most cells dense memory gets loaded with. it's one vec up to the
+// highest address, so a corrupt save could otherwise ask for terabytes
+const MAX_DENSE_LEN: usize = 1 << 26;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LoadError {
+  BadMagic,
+  UnsupportedVersion(u64),
+  Truncated,
+  TrailingData,
+  // a value that doesn't fit in the word type being loaded into
+  BadWord(String),
+  // what serde_json didn't like about it
+  BadJson(String),
+  MissingField(&'static str),
+  BadField(&'static str),
+  // dense memory_len over MAX_DENSE_LEN
+  TooBig(usize),
+}
+
+impl fmt::Display for LoadError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      LoadError::BadMagic => write!(f, "not a saved vm"),
+      LoadError::UnsupportedVersion(x) => write!(f, "unsupported version {}, expected {}", x, VERSION),
+      LoadError::Truncated => write!(f, "saved vm is truncated"),
+      LoadError::TrailingData => write!(f, "junk after the end of the saved vm"),
+      LoadError::BadWord(x) => write!(f, "bad word {}", x),
+      LoadError::BadJson(x) => write!(f, "bad json: {}", x),
+      LoadError::MissingField(x) => write!(f, "missing field {}", x),
+      LoadError::BadField(x) => write!(f, "bad value for field {}", x),
+      LoadError::TooBig(x) => write!(f, "{} words is too much dense memory to load", x),
+    }
+  }
+}
+
+impl error::Error for LoadError {}
+
+fn push_varint(out: &mut Vec<u8>, mut value: u64) {
+  while value >= 0x80 {
+    out.push((value as u8 & 0x7f) | 0x80);
+    value >>= 7;
+  }
+  out.push(value as u8);
+}
+
+fn push_word<W: Word>(out: &mut Vec<u8>, word: &W) {
+  let bytes = word.to_bytes();
+  push_varint(out, bytes.len() as u64);
+  out.extend(bytes);
+}
+
+struct Reader<'a> {
+  bytes: &'a [u8],
+  pos: usize,
+}
+
+impl<'a> Reader<'a> {
+  fn take(&mut self, count: usize) -> Result<&'a [u8], LoadError> {
+    if self.bytes.len() - self.pos < count {
+      return Err(LoadError::Truncated);
+    }
+    self.pos += count;
+    Ok(&self.bytes[self.pos - count..self.pos])
+  }
+
+  fn varint(&mut self) -> Result<u64, LoadError> {
+    let mut value = 0;
+    for shift in (0..64).step_by(7) {
+      let byte = self.take(1)?[0];
+      value |= u64::from(byte & 0x7f) << shift;
+      if byte & 0x80 == 0 {
+        return Ok(value);
+      }
+    }
+    Err(LoadError::BadField("varint"))
+  }
+
+  fn usize(&mut self, field: &'static str) -> Result<usize, LoadError> {
+    let value = self.varint()?;
+    if value > usize::MAX as u64 {
+      return Err(LoadError::BadField(field));
+    }
+    Ok(value as usize)
+  }
+
+  fn word<W: Word>(&mut self) -> Result<W, LoadError> {
+    let len = self.usize("word")?;
+    let bytes = self.take(len)?;
+    W::from_bytes(bytes).ok_or_else(|| LoadError::BadWord(format!("{:?}", bytes)))
+  }
+
+  fn words<W: Word>(&mut self, field: &'static str) -> Result<Vec<W>, LoadError> {
+    let len = self.usize(field)?;
+    // every word is at least a byte, so don't trust a length that says more
+    if len > self.bytes.len() - self.pos {
+      return Err(LoadError::Truncated);
+    }
+    (0..len).map(|_| self.word()).collect()
+  }
+
+  fn runs<W: Word>(&mut self) -> Result<Vec<(usize, Vec<W>)>, LoadError> {
+    let count = self.usize("memory")?;
+    // a run is at least a byte of start and a byte of length
+    if count > (self.bytes.len() - self.pos) / 2 {
+      return Err(LoadError::Truncated);
+    }
+    (0..count).map(|_| Ok((self.usize("memory")?, self.words("memory")?))).collect()
+  }
+}
+
+// memory as it's saved, checking every run fits inside len and the limit
+// before anything is allocated
+fn load_memory<W: Word>(
+  backend: Backend,
+  limit: Option<usize>,
+  len: usize,
+  runs: &[(usize, Vec<W>)],
+) -> Result<Memory<W>, LoadError> {
+  if backend == Backend::Dense && len > MAX_DENSE_LEN {
+    return Err(LoadError::TooBig(len));
+  }
+
+  let max = limit.map_or(len, |x| x.min(len));
+  for (start, run) in runs {
+    if start.checked_add(run.len()).is_none_or(|end| end > max) {
+      return Err(LoadError::BadField("memory"));
+    }
+  }
+
+  let mut mem = Memory::from_runs(backend, len, runs);
+  mem.set_limit(limit);
+  Ok(mem)
+}
+
+// numbers are kept as their text (serde_json's arbitrary_precision) so they
+// can be parsed as whatever word type is wanted, big ones included
+fn field<'a>(fields: &'a Map<String, Value>, name: &'static str) -> Result<&'a Value, LoadError> {
+  fields.get(name).ok_or(LoadError::MissingField(name))
+}
+
+fn json_word<W: Word>(value: &Value, name: &'static str) -> Result<W, LoadError> {
+  match value {
+    Value::Number(x) => x.to_string().parse().map_err(|_| LoadError::BadWord(x.to_string())),
+    _ => Err(LoadError::BadField(name)),
+  }
+}
+
+fn json_usize(value: &Value, name: &'static str) -> Result<usize, LoadError> {
+  match value {
+    Value::Number(x) => x.to_string().parse().map_err(|_| LoadError::BadField(name)),
+    _ => Err(LoadError::BadField(name)),
+  }
+}
+
+fn json_words<W: Word>(value: &Value, name: &'static str) -> Result<Vec<W>, LoadError> {
+  match value {
+    Value::Array(items) => items.iter().map(|x| json_word(x, name)).collect(),
+    _ => Err(LoadError::BadField(name)),
+  }
+}
+
+fn json_runs<W: Word>(value: &Value) -> Result<Vec<(usize, Vec<W>)>, LoadError> {
+  let runs = match value {
+    Value::Array(runs) => runs,
+    _ => return Err(LoadError::BadField("memory")),
+  };
+
+  let run = |value: &Value| match value {
+    Value::Array(pair) if pair.len() == 2 => Ok((json_usize(&pair[0], "memory")?, json_words(&pair[1], "memory")?)),
+    _ => Err(LoadError::BadField("memory")),
+  };
+  runs.iter().map(run).collect()
+}
+
+fn json_list<W: Word>(words: &[W]) -> String {
+  let words: Vec<String> = words.iter().map(|x| x.to_string()).collect();
+  format!("[{}]", words.join(","))
+}
+
+impl<W: Word> Snapshot<W> {
+  pub fn to_bytes(&self) -> Vec<u8> {
+    let mut out = MAGIC.to_vec();
+    push_varint(&mut out, VERSION);
+    push_varint(&mut out, self.instruction_ptr as u64);
+    push_word(&mut out, &self.relative_base);
+    out.push(self.halted as u8);
+    push_varint(&mut out, BACKENDS.iter().position(|(x, _)| *x == self.mem.backend()).unwrap() as u64);
+    push_varint(&mut out, self.mem.limit().map_or(0, |x| x as u64 + 1));
+
+    push_varint(&mut out, self.mem.len() as u64);
+    let runs = self.mem.runs();
+    push_varint(&mut out, runs.len() as u64);
+    for (start, run) in &runs {
+      push_varint(&mut out, *start as u64);
+      push_varint(&mut out, run.len() as u64);
+      for x in run {
+        push_word(&mut out, x);
+      }
+    }
+
+    push_varint(&mut out, self.input.len() as u64);
+    for x in &self.input {
+      push_word(&mut out, x);
+    }
+    out
+  }
+
+  pub fn from_bytes(bytes: &[u8]) -> Result<Snapshot<W>, LoadError> {
+    let mut reader = Reader { bytes, pos: 0 };
+    if reader.take(MAGIC.len()).ok() != Some(MAGIC) {
+      return Err(LoadError::BadMagic);
+    }
+
+    let version = reader.varint()?;
+    if version != 1 && version != VERSION {
+      return Err(LoadError::UnsupportedVersion(version));
+    }
+
+    let instruction_ptr = reader.usize("ip")?;
+    let relative_base = reader.word()?;
+    let halted = match reader.take(1)?[0] {
+      0 => false,
+      1 => true,
+      _ => return Err(LoadError::BadField("halted")),
+    };
+
+    let mem = if version == 1 {
+      Memory::new(&reader.words("memory")?)
+    } else {
+      let backend = match BACKENDS.get(reader.usize("backend")?) {
+        Some((x, _)) => *x,
+        None => return Err(LoadError::BadField("backend")),
+      };
+      let limit = match reader.usize("limit")? {
+        0 => None,
+        x => Some(x - 1),
+      };
+      let len = reader.usize("memory_len")?;
+      load_memory(backend, limit, len, &reader.runs()?)?
+    };
+    let input = reader.words("input")?;
+
+    if reader.pos != bytes.len() {
+      return Err(LoadError::TrailingData);
+    }
+
+    Ok(Snapshot {
+      mem,
+      instruction_ptr,
+      relative_base,
+      halted,
+      input: input.into_iter().collect(),
+    })
+  }
+
+  pub fn to_json(&self) -> String {
+    let input: Vec<W> = self.input.iter().cloned().collect();
+    let backend = BACKENDS.iter().find(|(x, _)| *x == self.mem.backend()).unwrap().1;
+    let limit = self.mem.limit().map_or("null".to_string(), |x| x.to_string());
+    let runs: Vec<String> = self.mem.runs().iter().map(|(start, run)| format!("[{},{}]", start, json_list(run))).collect();
+
+    format!(
+      "{{\"version\":{},\"ip\":{},\"relative_base\":{},\"halted\":{},\"backend\":\"{}\",\"limit\":{},\"memory_len\":{},\"memory\":[{}],\"input\":{}}}",
+      VERSION,
+      self.instruction_ptr,
+      self.relative_base,
+      self.halted,
+      backend,
+      limit,
+      self.mem.len(),
+      runs.join(","),
+      json_list(&input),
+    )
+  }
+
+  pub fn from_json(text: &str) -> Result<Snapshot<W>, LoadError> {
+    let fields = match serde_json::from_str(text) {
+      Ok(Value::Object(fields)) => fields,
+      Ok(_) => return Err(LoadError::BadJson("expected an object".to_string())),
+      Err(err) => return Err(LoadError::BadJson(err.to_string())),
+    };
+
+    let version = match field(&fields, "version")? {
+      Value::Number(x) => x.as_u64().ok_or(LoadError::BadField("version"))?,
+      _ => return Err(LoadError::BadField("version")),
+    };
+    if version != 1 && version != VERSION {
+      return Err(LoadError::UnsupportedVersion(version));
+    }
+
+    let halted = match field(&fields, "halted")? {
+      Value::Bool(x) => *x,
+      _ => return Err(LoadError::BadField("halted")),
+    };
+
+    let mem = if version == 1 {
+      Memory::new(&json_words(field(&fields, "memory")?, "memory")?)
+    } else {
+      let backend = match field(&fields, "backend")? {
+        Value::String(name) => BACKENDS.iter().find(|(_, x)| x == name).ok_or(LoadError::BadField("backend"))?.0,
+        _ => return Err(LoadError::BadField("backend")),
+      };
+      let limit = match field(&fields, "limit")? {
+        Value::Null => None,
+        x => Some(json_usize(x, "limit")?),
+      };
+      let len = json_usize(field(&fields, "memory_len")?, "memory_len")?;
+      load_memory(backend, limit, len, &json_runs(field(&fields, "memory")?)?)?
+    };
+
+    Ok(Snapshot {
+      mem,
+      instruction_ptr: json_usize(field(&fields, "ip")?, "ip")?,
+      relative_base: json_word(field(&fields, "relative_base")?, "relative_base")?,
+      halted,
+      input: json_words(field(&fields, "input")?, "input")?.into_iter().collect(),
+    })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  use num::bigint::BigInt;
+
+  use crate::fixtures::ADDER;
+  use crate::memory::Backend;
+  use crate::vm::VM;
+
+  fn half_run() -> VM {
+    let mut vm = VM::from_program(&ADDER);
+    vm.run_until_input(&[5]).unwrap();
+    vm.push_inputs(&[-300, 7]);
+    vm
+  }
+
+  #[test]
+  fn test_word_bytes() {
+    for x in &[0i64, 1, -1, 127, 128, -128, -129, 255, 1 << 40, i64::MIN, i64::MAX] {
+      let bytes = x.to_bytes();
+      assert_eq!(i64::from_bytes(&bytes), Some(*x));
+      assert_eq!(BigInt::from_bytes(&bytes), Some(BigInt::from(*x)));
+      assert_eq!(BigInt::from(*x).to_bytes(), bytes);
+    }
+
+    assert!(0i64.to_bytes().is_empty());
+    assert_eq!(128i64.to_bytes(), [128, 0]);
+    assert_eq!(i64::from_bytes(&i128::MAX.to_bytes()), None);
+  }
+
+  #[test]
+  fn test_binary_round_trip() {
+    let vm = half_run();
+    let bytes = vm.snapshot().to_bytes();
+    assert_eq!(&bytes[..5], b"ICVM\x02");
+
+    let mut loaded = VM::from_snapshot(&Snapshot::from_bytes(&bytes).unwrap());
+    assert_eq!(loaded.snapshot(), vm.snapshot());
+    assert_eq!(loaded.run(&[]), Ok(vec![-295]));
+    assert_eq!(loaded.pending_input(), &[7]);
+
+    // version 1 saves still load: ip 0, rb 0, not halted, memory [99]
+    let vm = VM::<i64>::from_snapshot(&Snapshot::from_bytes(b"ICVM\x01\0\0\0\x01\x01\x63\0").unwrap());
+    assert_eq!(vm.memory().to_vec(), [99]);
+  }
+
+  #[test]
+  fn test_sparse_memory() {
+    // add #1 #1 -> [2^34], leaving a single cell way past the program
+    let mut vm = VM::<i64>::from_memory(Memory::with_backend(Backend::Sparse, &[1101, 1, 1, 1 << 34, 99]));
+    vm.set_memory_limit(Some(1 << 40));
+    vm.run(&[]).unwrap();
+    let snapshot = vm.snapshot();
+
+    let bytes = snapshot.to_bytes();
+    assert!(bytes.len() < 64);
+    let loaded = Snapshot::<i64>::from_bytes(&bytes).unwrap();
+    assert_eq!(loaded, snapshot);
+    assert_eq!(loaded.mem.backend(), Backend::Sparse);
+    assert_eq!(loaded.mem.limit(), Some(1 << 40));
+
+    let json = snapshot.to_json();
+    assert_eq!(
+      json,
+      r#"{"version":2,"ip":4,"relative_base":0,"halted":true,"backend":"sparse","limit":1099511627776,"memory_len":17179869185,"memory":[[0,[1101,1,1,17179869184,99]],[17179869184,[2]]],"input":[]}"#,
+    );
+    let loaded = Snapshot::<i64>::from_json(&json).unwrap();
+    assert_eq!(loaded, snapshot);
+    assert_eq!(loaded.mem.backend(), Backend::Sparse);
+    assert_eq!(loaded.mem.limit(), Some(1 << 40));
+  }
+
+  #[test]
+  fn test_json_round_trip() {
+    let vm = half_run();
+    let json = vm.snapshot().to_json();
+    assert_eq!(
+      json,
+      r#"{"version":2,"ip":2,"relative_base":0,"halted":false,"backend":"paged","limit":null,"memory_len":14,"memory":[[0,[3,11,3,12,1,11,12,13,4,13,99,5]]],"input":[-300,7]}"#,
+    );
+
+    let loaded = Snapshot::<BigInt>::from_json(&json).unwrap();
+    let mut vm = VM::from_snapshot(&loaded);
+    assert_eq!(vm.run(&[]), Ok(vec![BigInt::from(-295)]));
+
+    // whitespace and field order don't matter, and version 1 still loads
+    let json = "{ \"memory\": [99], \"input\": [], \"halted\": true,\n \"relative_base\": -4, \"ip\": 0, \"version\": 1 }";
+    let vm = VM::<i64>::from_snapshot(&Snapshot::from_json(json).unwrap());
+    assert!(vm.is_halted());
+    assert_eq!(*vm.relative_base(), -4);
+  }
+
+  #[test]
+  fn test_load_errors() {
+    let bytes = half_run().snapshot().to_bytes();
+    let load = |bytes: &[u8]| Snapshot::<i64>::from_bytes(bytes).unwrap_err();
+
+    assert_eq!(load(b"ICVX\x01"), LoadError::BadMagic);
+    assert_eq!(load(b"ICVM\x03"), LoadError::UnsupportedVersion(3));
+    assert_eq!(load(&bytes[..bytes.len() - 1]), LoadError::Truncated);
+    assert_eq!(load(&[&bytes[..], &[0]].concat()), LoadError::TrailingData);
+
+    let json = |text: &str| Snapshot::<i64>::from_json(text).unwrap_err();
+    assert_eq!(json("{\"version\":7}"), LoadError::UnsupportedVersion(7));
+    assert_eq!(json("{\"version\":1,\"ip\":0}"), LoadError::MissingField("halted"));
+    assert!(matches!(json("{\"version\":1,"), LoadError::BadJson(_)));
+    assert!(matches!(json("[1]"), LoadError::BadJson(_)));
+    // a run that goes past memory_len
+    assert_eq!(
+      json(r#"{"version":2,"ip":0,"relative_base":0,"halted":false,"backend":"dense","limit":null,"memory_len":1,"memory":[[0,[1,2]]],"input":[]}"#),
+      LoadError::BadField("memory"),
+    );
+    // or past the limit
+    assert_eq!(
+      json(r#"{"version":2,"ip":0,"relative_base":0,"halted":false,"backend":"sparse","limit":4,"memory_len":1000,"memory":[[0,[1]],[500,[1]]],"input":[]}"#),
+      LoadError::BadField("memory"),
+    );
+    // dense memory far too big to allocate
+    assert_eq!(
+      json(r#"{"version":2,"ip":0,"relative_base":0,"halted":false,"backend":"dense","limit":null,"memory_len":1099511627777,"memory":[[1099511627776,[1]]],"input":[]}"#),
+      LoadError::TooBig(1099511627777),
+    );
+    // nesting too deep for the parser is an error rather than a crash
+    assert!(matches!(json(&"[".repeat(100_000)), LoadError::BadJson(_)));
+    assert_eq!(
+      json(r#"{"version":1,"ip":0,"relative_base":0,"halted":false,"memory":[1.5],"input":[]}"#),
+      LoadError::BadWord("1.5".to_string()),
+    );
+  }
+}
@@ -0,0 +1,405 @@
+use std::collections::{BTreeMap, HashMap};
+use std::error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::io::Write;
+use std::path::Path;
+
+use crate::error::VmError;
+use crate::io::Output;
+use crate::vm::VM;
+use crate::word::Word;
+
+// the most tiles a screen can be drawn with, counting the gaps between
+// them. anything bigger is almost certainly a stray triple a long way from
+// the rest, and would take forever to draw
+const MAX_TILES: u128 = 1 << 24;
+
+// the most pixels an image can have once it's scaled up, which is 192MB of
+// rgb and also keeps each side well inside a png's u32
+const MAX_PIXELS: u128 = 1 << 26;
+
+// a tile's character and colour
+type Style = (char, [u8; 3]);
+
+#[derive(Debug)]
+pub enum ScreenError {
+  // nothing has been drawn, and an image can't be zero pixels across
+  Empty,
+  // width and height in tiles, which come to more than MAX_TILES
+  TooBig { width: u128, height: u128 },
+  // width and height in pixels, which come to more than MAX_PIXELS
+  ImageTooBig { width: u128, height: u128 },
+  // tiles have to be at least one pixel across
+  ZeroScale,
+  Png(png::EncodingError),
+  Io(io::Error),
+}
+
+impl fmt::Display for ScreenError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      ScreenError::Empty => write!(f, "nothing has been drawn"),
+      ScreenError::TooBig { width, height } => write!(f, "screen is too big to draw at {}x{} tiles", width, height),
+      ScreenError::ImageTooBig { width, height } => write!(f, "image is too big at {}x{} pixels", width, height),
+      ScreenError::ZeroScale => write!(f, "can't draw tiles zero pixels across"),
+      ScreenError::Png(x) => write!(f, "{}", x),
+      ScreenError::Io(x) => write!(f, "{}", x),
+    }
+  }
+}
+
+impl error::Error for ScreenError {}
+
+impl From<png::EncodingError> for ScreenError {
+  fn from(err: png::EncodingError) -> ScreenError {
+    ScreenError::Png(err)
+  }
+}
+
+impl From<io::Error> for ScreenError {
+  fn from(err: io::Error) -> ScreenError {
+    ScreenError::Io(err)
+  }
+}
+
+// how each tile is drawn, as a character on the terminal and a colour in
+// an image. anything without an entry is drawn as the background, and so
+// is anywhere that's never been drawn on
+#[derive(Debug, Clone, PartialEq)]
+pub struct Palette {
+  tiles: BTreeMap<i64, Style>,
+  background: Style,
+}
+
+impl Palette {
+  pub fn new(background: char, colour: [u8; 3]) -> Palette {
+    Palette { tiles: BTreeMap::new(), background: (background, colour) }
+  }
+
+  // day 13's arcade cabinet: empty, wall, block, paddle and ball
+  pub fn arcade() -> Palette {
+    let mut palette = Palette::new(' ', [0, 0, 0]);
+    palette.set(1, '#', [160, 160, 160]);
+    palette.set(2, '=', [220, 120, 40]);
+    palette.set(3, '-', [80, 160, 255]);
+    palette.set(4, 'o', [255, 255, 255]);
+    palette
+  }
+
+  // day 11's hull: black and white panels
+  pub fn hull() -> Palette {
+    let mut palette = Palette::new('.', [0, 0, 0]);
+    palette.set(1, '#', [255, 255, 255]);
+    palette
+  }
+
+  pub fn set(&mut self, tile: i64, c: char, colour: [u8; 3]) {
+    self.tiles.insert(tile, (c, colour));
+  }
+
+  fn style<W: Word>(&self, tile: Option<&W>) -> Style {
+    let tile = tile.and_then(|x| x.to_i64());
+    tile.and_then(|x| self.tiles.get(&x)).copied().unwrap_or(self.background)
+  }
+}
+
+impl Default for Palette {
+  fn default() -> Palette {
+    Palette::new(' ', [0, 0, 0])
+  }
+}
+
+// an output sink that takes (x, y, tile) triples and draws them on a grid.
+// one coordinate, (-1, 0) to start with, is the score rather than a tile.
+// triples whose coordinates don't fit in an i64 are ignored
+pub struct Screen<W: Word = i64> {
+  tiles: HashMap<(i64, i64), W>,
+  score: Option<W>,
+  score_at: Option<(i64, i64)>,
+  partial: Vec<W>,
+}
+
+impl<W: Word> Screen<W> {
+  pub fn new() -> Screen<W> {
+    Screen { tiles: HashMap::new(), score: None, score_at: Some((-1, 0)), partial: Vec::new() }
+  }
+
+  // None if every coordinate is a tile
+  pub fn set_score_at(&mut self, at: Option<(i64, i64)>) {
+    self.score_at = at;
+  }
+
+  pub fn score(&self) -> Option<&W> {
+    self.score.as_ref()
+  }
+
+  pub fn get(&self, x: i64, y: i64) -> Option<&W> {
+    self.tiles.get(&(x, y))
+  }
+
+  pub fn tiles(&self) -> &HashMap<(i64, i64), W> {
+    &self.tiles
+  }
+
+  // where every tile of a kind is, e.g. the ball and paddle
+  pub fn find(&self, tile: &W) -> Vec<(i64, i64)> {
+    let mut found: Vec<(i64, i64)> = self.tiles.iter().filter(|(_, x)| *x == tile).map(|(at, _)| *at).collect();
+    found.sort_by_key(|(x, y)| (*y, *x));
+    found
+  }
+
+  // top left and bottom right of everything drawn, inclusive
+  pub fn bounds(&self) -> Option<((i64, i64), (i64, i64))> {
+    let xs = self.tiles.keys().map(|(x, _)| *x);
+    let ys = self.tiles.keys().map(|(_, y)| *y);
+    Some(((xs.clone().min()?, ys.clone().min()?), (xs.max()?, ys.max()?)))
+  }
+
+  // run vm until it wants input, drawing whatever it outputs on the way
+  pub fn update(&mut self, vm: &mut VM<W>, input: &[W]) -> Result<(), VmError<W>> {
+    for x in vm.run_until_input(input)? {
+      self.write(x);
+    }
+    Ok(())
+  }
+
+  fn rows(&self, palette: &Palette) -> Result<Vec<Vec<Style>>, ScreenError> {
+    let ((left, top), (right, bottom)) = match self.bounds() {
+      Some(x) => x,
+      None => return Ok(Vec::new()),
+    };
+
+    // in i128, so tiles at both i64::MIN and i64::MAX don't overflow
+    let width = (right as i128 - left as i128 + 1) as u128;
+    let height = (bottom as i128 - top as i128 + 1) as u128;
+    if width.checked_mul(height).is_none_or(|x| x > MAX_TILES) {
+      return Err(ScreenError::TooBig { width, height });
+    }
+
+    Ok((top..=bottom).map(|y| (left..=right).map(|x| palette.style(self.get(x, y))).collect()).collect())
+  }
+
+  // the grid, then the score on a line of its own if there is one
+  pub fn render(&self, palette: &Palette) -> Result<String, ScreenError> {
+    let mut out = String::new();
+    for row in self.rows(palette)? {
+      out.extend(row.iter().map(|(c, _)| c));
+      out.push('\n');
+    }
+    if let Some(score) = &self.score {
+      out.push_str(&format!("score: {}\n", score));
+    }
+    Ok(out)
+  }
+
+  // clear the terminal and redraw, for watching a game as it goes
+  pub fn draw(&self, palette: &Palette) -> Result<(), ScreenError> {
+    let mut stdout = io::stdout();
+    write!(stdout, "\x1b[H\x1b[2J{}", self.render(palette)?)?;
+    stdout.flush()?;
+    Ok(())
+  }
+
+  // every pixel as rgb, a row at a time, with each tile scale pixels across
+  fn pixels(&self, palette: &Palette, scale: usize) -> Result<(usize, usize, Vec<u8>), ScreenError> {
+    if scale == 0 {
+      return Err(ScreenError::ZeroScale);
+    }
+
+    let rows = self.rows(palette)?;
+    let (width, height) = (rows.first().ok_or(ScreenError::Empty)?.len(), rows.len());
+    // rows has at most MAX_TILES tiles, so this can't overflow
+    let (width, height) = (width as u128 * scale as u128, height as u128 * scale as u128);
+    if width.checked_mul(height).is_none_or(|x| x > MAX_PIXELS) {
+      return Err(ScreenError::ImageTooBig { width, height });
+    }
+    let width = width as usize;
+
+    let mut pixels = Vec::new();
+    for row in &rows {
+      for _ in 0..scale {
+        for (_, colour) in row {
+          for _ in 0..scale {
+            pixels.extend(colour);
+          }
+        }
+      }
+    }
+    Ok((width, height as usize, pixels))
+  }
+
+  pub fn to_ppm(&self, palette: &Palette, scale: usize) -> Result<Vec<u8>, ScreenError> {
+    let (width, height, pixels) = self.pixels(palette, scale)?;
+    let mut out = format!("P6\n{} {}\n255\n", width, height).into_bytes();
+    out.extend(pixels);
+    Ok(out)
+  }
+
+  pub fn to_png(&self, palette: &Palette, scale: usize) -> Result<Vec<u8>, ScreenError> {
+    let (width, height, pixels) = self.pixels(palette, scale)?;
+
+    let mut out = Vec::new();
+    let mut encoder = png::Encoder::new(&mut out, width as u32, height as u32);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(&pixels)?;
+    writer.finish()?;
+    Ok(out)
+  }
+
+  // as a png if the path ends in .png, otherwise as a ppm
+  pub fn save_image<P: AsRef<Path>>(&self, path: P, palette: &Palette, scale: usize) -> Result<(), ScreenError> {
+    let path = path.as_ref();
+    if path.extension().is_some_and(|x| x == "png") {
+      fs::write(path, self.to_png(palette, scale)?)?;
+    } else {
+      fs::write(path, self.to_ppm(palette, scale)?)?;
+    }
+    Ok(())
+  }
+}
+
+impl<W: Word> Default for Screen<W> {
+  fn default() -> Screen<W> {
+    Screen::new()
+  }
+}
+
+impl<W: Word> Output<W> for Screen<W> {
+  fn write(&mut self, value: W) {
+    self.partial.push(value);
+    if self.partial.len() < 3 {
+      return;
+    }
+
+    let mut values = std::mem::take(&mut self.partial).into_iter();
+    let (x, y, tile) = (values.next().unwrap(), values.next().unwrap(), values.next().unwrap());
+    let at = match (x.to_i64(), y.to_i64()) {
+      (Some(x), Some(y)) => (x, y),
+      _ => return,
+    };
+
+    if Some(at) == self.score_at {
+      self.score = Some(tile);
+    } else {
+      self.tiles.insert(at, tile);
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  use crate::asm::assemble;
+
+  // draws a wall, a ball and the score, then moves the paddle to wherever
+  // it's told
+  const GAME: &str = "
+          out #0
+          out #0
+          out #1
+          out #1
+          out #0
+          out #1
+          out #2
+          out #1
+          out #4
+          out #-1
+          out #0
+          out #1234
+    loop: in -> [x]
+          out [x]
+          out #2
+          out #3
+          jt #1 #loop
+    x:    .data 0
+  ";
+
+  fn game() -> (VM, Screen) {
+    let mut vm = VM::from_program(&assemble(GAME).unwrap());
+    let mut screen = Screen::new();
+    screen.update(&mut vm, &[]).unwrap();
+    (vm, screen)
+  }
+
+  #[test]
+  fn test_triples() {
+    let (mut vm, mut screen) = game();
+    assert_eq!(screen.get(1, 0), Some(&1));
+    assert_eq!(screen.get(2, 1), Some(&4));
+    assert_eq!(screen.get(-1, 0), None);
+    assert_eq!(screen.score(), Some(&1234));
+    assert_eq!(screen.bounds(), Some(((0, 0), (2, 1))));
+
+    screen.update(&mut vm, &[1, 0]).unwrap();
+    assert_eq!(screen.find(&3), [(0, 2), (1, 2)]);
+    assert_eq!(screen.bounds(), Some(((0, 0), (2, 2))));
+
+    // with no score coordinate it's just another tile
+    let mut vm = VM::<i64>::from_program(&assemble(GAME).unwrap());
+    let mut screen = Screen::new();
+    screen.set_score_at(None);
+    screen.update(&mut vm, &[]).unwrap();
+    assert_eq!(screen.score(), None);
+    assert_eq!(screen.get(-1, 0), Some(&1234));
+  }
+
+  #[test]
+  fn test_render() {
+    let (mut vm, mut screen) = game();
+    screen.update(&mut vm, &[2]).unwrap();
+    assert_eq!(screen.render(&Palette::arcade()).unwrap(), "## \n  o\n  -\nscore: 1234\n");
+
+    let mut palette = Palette::default();
+    palette.set(4, '*', [255, 0, 0]);
+    assert_eq!(screen.render(&palette).unwrap(), "   \n  *\n   \nscore: 1234\n");
+
+    assert_eq!(Screen::<i64>::new().render(&palette).unwrap(), "");
+
+    // stray tiles at either end of the coordinates can't be drawn
+    for (x, y) in [(i64::MIN, 0), (i64::MAX, 0), (0, 5000)] {
+      screen.write(x);
+      screen.write(y);
+      screen.write(1);
+    }
+    assert!(matches!(screen.render(&palette), Err(ScreenError::TooBig { height: 5001, .. })));
+    assert!(matches!(screen.to_png(&palette, 1), Err(ScreenError::TooBig { width, .. }) if width == 1 << 64));
+  }
+
+  #[test]
+  fn test_images() {
+    let (_, screen) = game();
+    let palette = Palette::hull();
+
+    let ppm = screen.to_ppm(&palette, 2).unwrap();
+    assert!(ppm.starts_with(b"P6\n6 4\n255\n"));
+    assert_eq!(ppm.len(), 11 + 6 * 4 * 3);
+    // the top row is two white tiles then a black one
+    assert_eq!(&ppm[11..11 + 18], [[255; 12].as_ref(), &[0; 6]].concat().as_slice());
+
+    // the same pixels come back out of the png
+    let png = screen.to_png(&palette, 2).unwrap();
+    let mut reader = png::Decoder::new(png.as_slice()).read_info().unwrap();
+    let mut pixels = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut pixels).unwrap();
+    assert_eq!((info.width, info.height, info.color_type), (6, 4, png::ColorType::Rgb));
+    assert_eq!(pixels, &ppm[11..]);
+
+    // an image has to have something in it
+    let empty = Screen::<i64>::new();
+    assert!(matches!(empty.to_png(&palette, 2), Err(ScreenError::Empty)));
+    assert!(matches!(empty.to_ppm(&palette, 2), Err(ScreenError::Empty)));
+
+    // nor zero sized pixels, or more of them than will fit in memory
+    assert!(matches!(screen.to_png(&palette, 0), Err(ScreenError::ZeroScale)));
+    assert!(matches!(screen.to_ppm(&palette, 0), Err(ScreenError::ZeroScale)));
+    assert!(matches!(
+      screen.to_png(&palette, usize::MAX),
+      Err(ScreenError::ImageTooBig { width, .. }) if width == 3 * usize::MAX as u128
+    ));
+  }
+}
@@ -0,0 +1,315 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use rayon::prelude::*;
+
+use crate::error::VmError;
+use crate::patch::PatchError;
+use crate::vm::VM;
+use crate::word::Word;
+
+// one part of a candidate. a candidate is every dimension's values one
+// after the other, e.g. [Range(0, 99), Range(0, 99)] gives [noun, verb]
+#[derive(Debug, Clone, PartialEq)]
+pub enum Dimension<W> {
+  // every value from start to end, including end. both ends have to fit in
+  // an i64, anything else is an empty range
+  Range(W, W),
+  Values(Vec<W>),
+  // every ordering of the values, each one giving values.len() words
+  Permutations(Vec<W>),
+  // the inner dimension's values, which patched also writes into memory
+  // starting at addr, e.g. day 2's noun and verb at 1 and 2
+  Patch(usize, Box<Dimension<W>>),
+}
+
+// where a dimension is up to while its options are being gone through
+#[derive(Debug, Clone)]
+enum Position {
+  Value(i64),
+  Index(usize),
+  // indexes into the values, in the order they're used
+  Order(Vec<usize>),
+}
+
+impl<W: Word> Dimension<W> {
+  // the first option, or None if there aren't any
+  fn start(&self) -> Option<Position> {
+    match self {
+      Dimension::Range(start, end) => {
+        let (start, end) = (start.to_i64()?, end.to_i64()?);
+        Some(Position::Value(start)).filter(|_| start <= end)
+      },
+      Dimension::Values(values) => Some(Position::Index(0)).filter(|_| !values.is_empty()),
+      Dimension::Permutations(values) => Some(Position::Order((0..values.len()).collect())),
+      Dimension::Patch(_, inner) => inner.start(),
+    }
+  }
+
+  // move on to the next option, or false if that was the last one.
+  // permutations go in lexicographic order of position, so [1, 2, 3] starts
+  // [1, 2, 3], [1, 3, 2]
+  fn advance(&self, position: &mut Position) -> bool {
+    match (self, position) {
+      (Dimension::Range(_, end), Position::Value(x)) => {
+        let more = end.to_i64().is_some_and(|end| *x < end);
+        if more {
+          *x += 1;
+        }
+        more
+      },
+      (Dimension::Values(values), Position::Index(n)) => {
+        *n += 1;
+        *n < values.len()
+      },
+      (Dimension::Permutations(_), Position::Order(order)) => {
+        // the rightmost place that can be swapped for something bigger to
+        // its right, which is then put back in ascending order
+        let pivot = match (1..order.len()).rev().find(|&i| order[i - 1] < order[i]) {
+          Some(i) => i - 1,
+          None => return false,
+        };
+        let swap = (pivot + 1..order.len()).rev().find(|&i| order[i] > order[pivot]).unwrap();
+        order.swap(pivot, swap);
+        order[pivot + 1..].reverse();
+        true
+      },
+      (Dimension::Patch(_, inner), position) => inner.advance(position),
+      _ => unreachable!("position from a different dimension"),
+    }
+  }
+
+  // how many words each option is
+  fn width(&self) -> usize {
+    match self {
+      Dimension::Range(_, _) | Dimension::Values(_) => 1,
+      Dimension::Permutations(values) => values.len(),
+      Dimension::Patch(_, inner) => inner.width(),
+    }
+  }
+
+  // the option at position, added on to out
+  fn option(&self, position: &Position, out: &mut Vec<W>) {
+    match (self, position) {
+      (Dimension::Range(_, _), Position::Value(x)) => out.push(W::from_i64(*x)),
+      (Dimension::Values(values), Position::Index(n)) => out.push(values[*n].clone()),
+      (Dimension::Permutations(values), Position::Order(order)) => out.extend(order.iter().map(|n| values[*n].clone())),
+      (Dimension::Patch(_, inner), position) => inner.option(position, out),
+      _ => unreachable!("position from a different dimension"),
+    }
+  }
+}
+
+// every candidate in a space, in order, with the last dimension changing
+// fastest. they're worked out one at a time as they're needed, so a space
+// too big to ever get to the end of is fine
+pub struct Candidates<'a, W> {
+  space: &'a [Dimension<W>],
+  // where each dimension is up to, or None once they've all been given out
+  positions: Option<Vec<Position>>,
+}
+
+impl<'a, W: Word> Iterator for Candidates<'a, W> {
+  type Item = Vec<W>;
+
+  fn next(&mut self) -> Option<Vec<W>> {
+    let positions = self.positions.as_mut()?;
+
+    let mut candidate = Vec::new();
+    for (dimension, position) in self.space.iter().zip(positions.iter()) {
+      dimension.option(position, &mut candidate);
+    }
+
+    // count up like an odometer, wrapping each dimension that runs out
+    // back to its start. once they've all wrapped that's everything
+    let mut more = false;
+    for (dimension, position) in self.space.iter().zip(positions.iter_mut()).rev() {
+      if dimension.advance(position) {
+        more = true;
+        break;
+      }
+      *position = dimension.start().unwrap();
+    }
+    if !more {
+      self.positions = None;
+    }
+
+    Some(candidate)
+  }
+}
+
+pub fn candidates<W: Word>(space: &[Dimension<W>]) -> Candidates<'_, W> {
+  let positions = space.iter().map(|x| x.start()).collect();
+  Candidates { space, positions }
+}
+
+// a copy of vm with every Patch dimension's part of candidate written into
+// memory. candidate has to be from space
+pub fn patched<W: Word>(vm: &VM<W>, space: &[Dimension<W>], candidate: &[W]) -> Result<VM<W>, PatchError> {
+  let mut vm = vm.clone();
+  let mut values = candidate;
+
+  for dimension in space {
+    let (these, rest) = values.split_at(dimension.width());
+    if let Dimension::Patch(addr, _) = dimension {
+      for (n, value) in these.iter().enumerate() {
+        vm.patch(addr + n, value.clone())?;
+      }
+    }
+    values = rest;
+  }
+  Ok(vm)
+}
+
+// run a candidate's vm until it halts, handing back the finished vm and its
+// output. it gets budget more instructions, so one that loops forever gives
+// up with VmError::BudgetExhausted rather than holding up the whole search
+pub fn run_candidate<W: Word>(mut vm: VM<W>, budget: u64) -> Result<(VM<W>, Vec<W>), VmError<W>> {
+  vm.set_budget(Some(budget));
+  let output = vm.run(&[])?;
+  Ok((vm, output))
+}
+
+// try every candidate in parallel, returning the first one, in the space's
+// order, whose score matches target. score is None for a candidate that
+// didn't work out, e.g. because its vm failed
+pub fn search_first<W, S, F, P>(space: &[Dimension<W>], score: F, target: P) -> Option<(Vec<W>, S)>
+where
+  W: Word + Send + Sync,
+  S: Send,
+  F: Fn(&[W]) -> Option<S> + Sync,
+  P: Fn(&S) -> bool + Sync,
+{
+  // candidates are handed out in order, so once one matches nothing after
+  // it needs handing out, while everything before it already has been
+  let found = AtomicUsize::new(usize::MAX);
+  candidates(space)
+    .enumerate()
+    .take_while(|(n, _)| *n < found.load(Ordering::SeqCst))
+    .par_bridge()
+    .filter_map(|(n, candidate)| {
+      let x = score(&candidate).filter(|x| target(x))?;
+      found.fetch_min(n, Ordering::SeqCst);
+      Some((n, candidate, x))
+    })
+    .min_by_key(|(n, _, _)| *n)
+    .map(|(_, candidate, x)| (candidate, x))
+}
+
+// try every candidate in parallel, returning the one with the highest
+// score. ties go to whichever comes first in the space
+pub fn search_best<W, S, F>(space: &[Dimension<W>], score: F) -> Option<(Vec<W>, S)>
+where
+  W: Word + Send + Sync,
+  S: Ord + Send,
+  F: Fn(&[W]) -> Option<S> + Sync,
+{
+  candidates(space)
+    .enumerate()
+    .par_bridge()
+    .filter_map(|(n, candidate)| score(&candidate).map(|x| (n, candidate, x)))
+    .max_by(|a, b| a.2.cmp(&b.2).then(b.0.cmp(&a.0)))
+    .map(|(_, candidate, x)| (candidate, x))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  use crate::asm::assemble;
+
+  #[test]
+  fn test_candidates() {
+    let all = |space: &[Dimension<i64>]| candidates(space).collect::<Vec<_>>();
+
+    let space = [Dimension::Range(1, 2), Dimension::Values(vec![7, 8])];
+    assert_eq!(all(&space), [[1, 7], [1, 8], [2, 7], [2, 8]]);
+
+    let space = [Dimension::Permutations(vec![1, 2, 3])];
+    assert_eq!(all(&space), [[1, 2, 3], [1, 3, 2], [2, 1, 3], [2, 3, 1], [3, 1, 2], [3, 2, 1]]);
+
+    let space = [Dimension::Values(vec![0]), Dimension::Patch(5, Box::new(Dimension::Permutations(vec![1, 2])))];
+    assert_eq!(all(&space), [[0, 1, 2], [0, 2, 1]]);
+
+    assert_eq!(all(&[]), [Vec::<i64>::new()]);
+    assert!(all(&[Dimension::Range(5, 4)]).is_empty());
+    assert_eq!(all(&[Dimension::Range(i64::MAX - 1, i64::MAX)]), [[i64::MAX - 1], [i64::MAX]]);
+
+    // nothing is worked out until it's asked for, so spaces with more
+    // candidates than a usize can count are fine
+    let space = [Dimension::Range(i64::MIN, i64::MAX), Dimension::Range(0, 1)];
+    assert_eq!(candidates(&space).nth(3), Some(vec![i64::MIN + 1, 1]));
+    let space = [Dimension::Permutations((0..21).collect())];
+    let mut last = (0..21).collect::<Vec<i64>>();
+    last.swap(19, 20);
+    assert_eq!(candidates(&space).nth(1), Some(last));
+  }
+
+  #[test]
+  fn test_patched() {
+    let vm = VM::<i64>::from_program(&[1, 0, 0, 0, 99]);
+    let space = [Dimension::Values(vec![7]), Dimension::Patch(1, Box::new(Dimension::Permutations(vec![2, 3])))];
+
+    let vm = patched(&vm, &space, &[7, 3, 2]).unwrap();
+    assert_eq!(vm.memory().to_vec(), [1, 3, 2, 0, 99]);
+    assert!(patched(&vm, &[Dimension::Patch(5, Box::new(Dimension::Values(vec![1])))], &[1]).is_err());
+  }
+
+  #[test]
+  fn test_search_first() {
+    // mul #noun #verb -> [0], looking for the first noun and verb that give 42
+    let vm = VM::from_program(&[1102, 0, 0, 0, 99]);
+    let space = [
+      Dimension::Patch(1, Box::new(Dimension::Range(0, 99))),
+      Dimension::Patch(2, Box::new(Dimension::Range(0, 99))),
+    ];
+
+    let score = |candidate: &[i64]| {
+      let vm = patched(&vm, &space, candidate).ok()?;
+      let (vm, _) = run_candidate(vm, 100).ok()?;
+      Some(vm.peek(0))
+    };
+
+    assert_eq!(search_first(&space, score, |x| *x == 42), Some((vec![1, 42], 42)));
+    assert_eq!(search_first(&space, score, |x| *x == 99 * 99), Some((vec![99, 99], 9801)));
+    assert_eq!(search_first(&space, score, |x| *x == 10007), None);
+
+    // a candidate that never halts gives up rather than holding up the search
+    let spin = VM::<i64>::from_program(&[1105, 1, 0]);
+    assert_eq!(run_candidate(spin, 100).err(), Some(VmError::BudgetExhausted { ip: 0, steps: 100 }));
+  }
+
+  #[test]
+  fn test_search_best() {
+    // outputs 100a + 10b + c, so the best is the largest permutation
+    let program = assemble(
+      "
+          in -> [a]
+          in -> [b]
+          in -> [c]
+          mul [a] #100 -> [a]
+          mul [b] #10 -> [b]
+          add [a] [b] -> [a]
+          add [a] [c] -> [a]
+          out [a]
+          hlt
+      a:  .data 0
+      b:  .data 0
+      c:  .data 0
+      ",
+    );
+
+    let vm = VM::<i64>::from_program(&program.unwrap());
+    let space = [Dimension::Permutations(vec![3, 1, 2])];
+    let best = search_best(&space, |candidate| {
+      let mut vm = vm.clone();
+      vm.push_inputs(candidate);
+      let (_, output) = run_candidate(vm, 100).ok()?;
+      output.first().copied()
+    });
+
+    assert_eq!(best, Some((vec![3, 2, 1], 321)));
+
+    // everything scores the same, so the first candidate wins
+    assert_eq!(search_best(&space, |_| Some(0)), Some((vec![3, 1, 2], 0)));
+  }
+}
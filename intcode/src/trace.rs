@@ -0,0 +1,192 @@
+use std::io;
+use std::sync::{Arc, Mutex};
+
+use crate::instruction::Instruction;
+use crate::word::Word;
+
+// everything one instruction did
+#[derive(Debug, Clone, PartialEq)]
+pub struct Event<W> {
+  pub ip: usize,
+  pub instruction: Instruction<W>,
+  // values of the params that get read, in order
+  pub operands: Vec<W>,
+  // (address, value) of every cell read to get those operands
+  pub reads: Vec<(usize, W)>,
+  // (address, new value) of every cell written
+  pub writes: Vec<(usize, W)>,
+  pub next_ip: usize,
+  pub relative_base: W,
+}
+
+// gets told about every instruction the vm runs. before is called once an
+// instruction has been decoded, after once it has finished. an instruction
+// that fails never gets an after, and one waiting for input gets neither
+// until there's input for it
+pub trait Tracer<W: Word> {
+  fn before(&mut self, _ip: usize, _instruction: &Instruction<W>) {}
+  fn after(&mut self, event: &Event<W>);
+}
+
+// lets the caller hang on to a tracer the vm owns, to look at it afterwards
+impl<W: Word, T: Tracer<W>> Tracer<W> for Arc<Mutex<T>> {
+  fn before(&mut self, ip: usize, instruction: &Instruction<W>) {
+    self.lock().unwrap().before(ip, instruction);
+  }
+
+  fn after(&mut self, event: &Event<W>) {
+    self.lock().unwrap().after(event);
+  }
+}
+
+// one json object per instruction, e.g.
+// {"ip":2,"instr":"add [9] #1 -> [9]","opcode":"add","operands":[4,1],"reads":[[9,4]],"writes":[[9,5]],"next_ip":6,"rb":0}
+//
+// if writing fails, e.g. the trace is piped into head, the error is kept and
+// nothing more is written. the vm carries on regardless
+pub struct JsonTracer<O: io::Write> {
+  out: O,
+  error: Option<io::Error>,
+}
+
+impl<O: io::Write> JsonTracer<O> {
+  pub fn new(out: O) -> JsonTracer<O> {
+    JsonTracer { out, error: None }
+  }
+
+  // the first write that failed, if one has
+  pub fn error(&self) -> Option<&io::Error> {
+    self.error.as_ref()
+  }
+
+  pub fn into_inner(self) -> O {
+    self.out
+  }
+}
+
+fn json_cells<W: Word>(cells: &[(usize, W)]) -> String {
+  let cells: Vec<String> = cells.iter().map(|(addr, x)| format!("[{},{}]", addr, x)).collect();
+  format!("[{}]", cells.join(","))
+}
+
+impl<W: Word, O: io::Write> Tracer<W> for JsonTracer<O> {
+  fn after(&mut self, event: &Event<W>) {
+    if self.error.is_some() {
+      return;
+    }
+
+    let operands: Vec<String> = event.operands.iter().map(|x| x.to_string()).collect();
+
+    let result = writeln!(
+      self.out,
+      "{{\"ip\":{},\"instr\":\"{}\",\"opcode\":\"{}\",\"operands\":[{}],\"reads\":{},\"writes\":{},\"next_ip\":{},\"rb\":{}}}",
+      event.ip,
+      event.instruction,
+      event.instruction.opcode,
+      operands.join(","),
+      json_cells(&event.reads),
+      json_cells(&event.writes),
+      event.next_ip,
+      event.relative_base,
+    );
+    self.error = result.err();
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  use crate::instruction::Opcode;
+  use crate::vm::{State, VM};
+
+  struct Recorder {
+    before: Vec<(usize, Opcode)>,
+    events: Vec<Event<i64>>,
+  }
+
+  impl Tracer<i64> for Recorder {
+    fn before(&mut self, ip: usize, instruction: &Instruction<i64>) {
+      self.before.push((ip, instruction.opcode));
+    }
+
+    fn after(&mut self, event: &Event<i64>) {
+      self.events.push(event.clone());
+    }
+  }
+
+  #[test]
+  fn test_events() {
+    let recorder = Arc::new(Mutex::new(Recorder { before: Vec::new(), events: Vec::new() }));
+
+    // in -> [9], add [9] #1 -> [9], out [9], hlt
+    let mut vm = VM::<i64>::from_program(&[3, 9, 1001, 9, 1, 9, 4, 9, 99, 0]);
+    vm.set_tracer(Box::new(recorder.clone()));
+    // the in waiting for input isn't traced until it runs
+    assert_eq!(vm.step(), State::AwaitingInput);
+    assert_eq!(vm.run(&[4]), Ok(vec![5]));
+
+    let recorder = recorder.lock().unwrap();
+    assert_eq!(
+      recorder.before,
+      [(0, Opcode::In), (2, Opcode::Add), (6, Opcode::Out), (8, Opcode::Halt)],
+    );
+
+    let add = &recorder.events[1];
+    assert_eq!(add.operands, [4, 1]);
+    assert_eq!(add.reads, [(9, 4)]);
+    assert_eq!(add.writes, [(9, 5)]);
+    assert_eq!(add.next_ip, 6);
+
+    assert_eq!(recorder.events[0].writes, [(9, 4)]);
+    assert_eq!(recorder.events[2].operands, [5]);
+  }
+
+  #[test]
+  fn test_relative_and_jumps() {
+    let recorder = Arc::new(Mutex::new(Recorder { before: Vec::new(), events: Vec::new() }));
+
+    // arb #10, jf rel[-1] #7, hlt, hlt... with [9] = 0
+    let mut vm = VM::<i64>::from_program(&[109, 10, 1206, -1, 7, 99, 99, 99, 0, 0]);
+    vm.set_tracer(Box::new(recorder.clone()));
+    vm.run(&[]).unwrap();
+
+    let recorder = recorder.lock().unwrap();
+    assert_eq!(recorder.events[0].relative_base, 10);
+    assert_eq!(recorder.events[1].reads, [(9, 0)]);
+    assert_eq!(recorder.events[1].next_ip, 7);
+  }
+
+  #[test]
+  fn test_json_lines() {
+    let tracer = Arc::new(Mutex::new(JsonTracer::new(Vec::new())));
+    let mut vm = VM::<i64>::from_program(&[3, 9, 1001, 9, 1, 9, 4, 9, 99, 0]);
+    vm.set_tracer(Box::new(tracer.clone()));
+    vm.run(&[4]).unwrap();
+    drop(vm);
+
+    let out = Arc::try_unwrap(tracer).ok().unwrap().into_inner().unwrap().into_inner();
+    let lines: Vec<&str> = std::str::from_utf8(&out).unwrap().lines().collect();
+    assert_eq!(lines.len(), 4);
+    assert_eq!(
+      lines[1],
+      r#"{"ip":2,"instr":"add [9] #1 -> [9]","opcode":"add","operands":[4,1],"reads":[[9,4]],"writes":[[9,5]],"next_ip":6,"rb":0}"#,
+    );
+    assert_eq!(
+      lines[3],
+      r#"{"ip":8,"instr":"hlt","opcode":"hlt","operands":[],"reads":[],"writes":[],"next_ip":8,"rb":0}"#,
+    );
+  }
+
+  #[test]
+  fn test_json_write_error() {
+    // room for the first line and part of the second
+    let tracer = Arc::new(Mutex::new(JsonTracer::new(io::Cursor::new([0u8; 150]))));
+    let mut vm = VM::<i64>::from_program(&[3, 9, 1001, 9, 1, 9, 4, 9, 99, 0]);
+    vm.set_tracer(Box::new(tracer.clone()));
+    assert_eq!(vm.run(&[4]), Ok(vec![5]));
+
+    let tracer = tracer.lock().unwrap();
+    assert_eq!(tracer.error().map(|x| x.kind()), Some(io::ErrorKind::WriteZero));
+  }
+}
@@ -0,0 +1,1085 @@
+/*
+= opcodes =
+add     1,X,Y,D     $D = $X + $Y
+mul     2,X,Y,D     $D = $X * $Y
+in      3,D         $D = input
+out     4,X         output $X
+jt      5,X,T       if $X != 0, jump to $T
+jf      6,X,T       if $X == 0, jump to $T
+lt      7,X,Y,D     $D = $X < $Y
+eq      8,X,Y,D     $D = $X == $Y
+arb     9,X         relative base += $X
+hlt     99          halt
+
+= parameter modes =
+0 - position mode   - parameter is a memory address
+1 - immediate mode  - parameter is a value
+2 - relative mode   - parameter is an address offset from the relative base
+*/
+
+use std::collections::VecDeque;
+use std::convert::TryFrom;
+use std::time::{Duration, Instant};
+
+use crate::error::VmError;
+use crate::instruction::{decode, decode_lenient, DecodeError, Instruction, Mode, Opcode, Param};
+use crate::io::{Input, Output};
+use crate::memory::{LimitExceeded, Memory};
+use crate::patch::PatchError;
+use crate::trace::{Event, Tracer};
+use crate::word::Word;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum State<W: Word = i64> {
+  Halted,
+  AwaitingInput,
+  Output(W),
+  Faulted(VmError<W>),
+}
+
+pub struct VM<W: Word = i64> {
+  mem: Memory<W>,
+  instruction_ptr: usize,
+  relative_base: W,
+  halted: bool,
+  input: VecDeque<W>,
+  tracer: Option<Box<dyn Tracer<W> + Send + Sync>>,
+  steps: u64,
+  budget: Option<u64>,
+  deadline: Option<Instant>,
+  // decoded instructions by address, up to CACHE_LIMIT. None if it's been
+  // turned off
+  cache: Option<Vec<Option<Decoded>>>,
+  lenient: bool,
+  // see fast_forward
+  fast: bool,
+  // how much memory the program was loaded with, see patch
+  image_len: usize,
+}
+
+// looking at the clock every instruction would be slow, so the deadline is
+// only checked this often
+const DEADLINE_CHECK_INTERVAL: u64 = 1024;
+
+type Decoded = (Opcode, [Mode; 3]);
+
+// instructions at or past here are decoded every time rather than cached,
+// so jumping a long way out doesn't grow the cache to match
+const CACHE_LIMIT: usize = 1 << 20;
+
+// a decoded instruction, along with where it came from so errors can point
+// back at it
+struct Op<W: Word> {
+  ip: usize,
+  instruction: W,
+  opcode: Opcode,
+  modes: [Mode; 3],
+}
+
+// what a tracer gets told about an instruction before it runs, kept so the
+// rest of the event can be filled in afterwards
+struct Pending<W> {
+  instruction: Instruction<W>,
+  operands: Vec<W>,
+  reads: Vec<(usize, W)>,
+  write: Option<usize>,
+}
+
+impl<W: Word> VM<W> {
+  fn write(&mut self, op: &Op<W>, value: W, dest: usize) -> Result<(), VmError<W>> {
+    self.forget(dest);
+    self.mem.write(dest, value).map_err(|err| VmError::MemoryLimit {
+      ip: op.ip,
+      instruction: op.instruction.clone(),
+      address: err.addr,
+      limit: err.limit,
+    })
+  }
+
+  fn get(&self, src: usize) -> W {
+    self.mem.read(src)
+  }
+
+  // a write to an instruction means it has to be decoded again
+  fn forget(&mut self, addr: usize) {
+    if let Some(slot) = self.cache.as_mut().and_then(|x| x.get_mut(addr)) {
+      *slot = None;
+    }
+  }
+
+  fn decode(&mut self) -> Result<Op<W>, VmError<W>> {
+    let ip = self.instruction_ptr;
+    if ip >= self.mem.len() {
+      return Err(VmError::PcOutOfBounds { ip });
+    }
+    let instruction = self.mem.read(ip);
+
+    let cached = self.cache.as_ref().and_then(|x| x.get(ip).copied().flatten());
+    let (opcode, modes) = match cached {
+      Some(x) => x,
+      None => {
+        let decoded = if self.lenient { decode_lenient(&instruction) } else { decode(&instruction) };
+        let decoded = match decoded {
+          Ok(x) => x,
+          Err(DecodeError::UnknownOpcode) => return Err(VmError::UnknownOpcode { ip, instruction }),
+          Err(DecodeError::InvalidMode(mode)) => return Err(VmError::InvalidMode { ip, instruction, mode }),
+          Err(DecodeError::ExtraModeDigits) => return Err(VmError::ExtraModeDigits { ip, instruction }),
+          Err(DecodeError::ImmediateWriteTarget) => {
+            return Err(VmError::ImmediateWriteTarget { ip, instruction })
+          },
+        };
+
+        if let Some(cache) = self.cache.as_mut().filter(|_| ip < CACHE_LIMIT) {
+          if cache.len() <= ip {
+            cache.resize(ip + 1, None);
+          }
+          cache[ip] = Some(decoded);
+        }
+        decoded
+      },
+    };
+
+    if ip + opcode.param_count() >= self.mem.len() {
+      return Err(VmError::PcOutOfBounds { ip });
+    }
+
+    Ok(Op { ip, instruction, opcode, modes })
+  }
+
+  // turn a word into a memory address
+  fn to_addr(&self, op: &Op<W>, address: W) -> Result<usize, VmError<W>> {
+    if address < W::zero() {
+      return Err(VmError::NegativeAddress { ip: op.ip, instruction: op.instruction.clone(), address });
+    }
+
+    // usize::MAX is a real usize, but memory can't go up to it, see
+    // Memory::write
+    match address.to_usize().filter(|x| *x < usize::MAX) {
+      Some(addr) => Ok(addr),
+      None => Err(VmError::AddressOutOfRange { ip: op.ip, instruction: op.instruction.clone(), address }),
+    }
+  }
+
+  fn relative(&self, op: &Op<W>, offset: &W) -> Result<usize, VmError<W>> {
+    match self.relative_base.checked_add(offset) {
+      Some(addr) => self.to_addr(op, addr),
+      None => Err(VmError::Overflow { ip: op.ip, instruction: op.instruction.clone() }),
+    }
+  }
+
+  fn param(&self, op: &Op<W>, n: usize) -> W {
+    self.mem.read(op.ip + 1 + n)
+  }
+
+  fn get_value(&mut self, op: &Op<W>, n: usize) -> Result<W, VmError<W>> {
+    let value = self.param(op, n);
+    match op.modes[n] {
+      Mode::Position => {
+        let addr = self.to_addr(op, value)?;
+        Ok(self.get(addr))
+      },
+      Mode::Relative => {
+        let addr = self.relative(op, &value)?;
+        Ok(self.get(addr))
+      },
+      Mode::Immediate => Ok(value),
+    }
+  }
+
+  fn get_addr(&self, op: &Op<W>, n: usize) -> Result<usize, VmError<W>> {
+    let value = self.param(op, n);
+    match op.modes[n] {
+      Mode::Position => self.to_addr(op, value),
+      Mode::Relative => self.relative(op, &value),
+
+      // decode already turns these away
+      Mode::Immediate => Err(VmError::ImmediateWriteTarget { ip: op.ip, instruction: op.instruction.clone() }),
+    }
+  }
+
+  // where a param points, without any of the error handling. only used
+  // for tracing, if it doesn't resolve the instruction is about to fail
+  fn resolve(&self, param: &Param<W>) -> Option<usize> {
+    match param {
+      Param::Position(x) => x.to_usize(),
+      Param::Relative(x) => self.relative_base.checked_add(x)?.to_usize(),
+      Param::Immediate(_) => None,
+    }
+  }
+
+  fn trace_before(&mut self, op: &Op<W>) -> Pending<W> {
+    let count = op.opcode.param_count();
+    let params: Vec<Param<W>> = (0..count)
+      .map(|n| Param::new(op.modes[n], self.param(op, n)))
+      .collect();
+
+    let read_count = if op.opcode.writes() { count - 1 } else { count };
+    let mut operands = Vec::new();
+    let mut reads = Vec::new();
+    for param in &params[..read_count] {
+      match (param, self.resolve(param)) {
+        (Param::Immediate(x), _) => operands.push(x.clone()),
+        (_, Some(addr)) => {
+          let value = self.peek(addr);
+          operands.push(value.clone());
+          reads.push((addr, value));
+        },
+        (_, None) => (),
+      }
+    }
+
+    let write = params[read_count..].first().and_then(|x| self.resolve(x));
+    let instruction = Instruction { opcode: op.opcode, params };
+
+    if let Some(tracer) = self.tracer.as_mut() {
+      tracer.before(op.ip, &instruction);
+    }
+
+    Pending { instruction, operands, reads, write }
+  }
+
+  fn trace_after(&mut self, ip: usize, pending: Pending<W>) {
+    let writes = match pending.write {
+      Some(addr) => vec![(addr, self.peek(addr))],
+      None => Vec::new(),
+    };
+
+    let event = Event {
+      ip,
+      instruction: pending.instruction,
+      operands: pending.operands,
+      reads: pending.reads,
+      writes,
+      next_ip: self.instruction_ptr,
+      relative_base: self.relative_base.clone(),
+    };
+
+    if let Some(tracer) = self.tracer.as_mut() {
+      tracer.after(&event);
+    }
+  }
+
+  // have every instruction reported to tracer from now on
+  pub fn set_tracer(&mut self, tracer: Box<dyn Tracer<W> + Send + Sync>) {
+    self.tracer = Some(tracer);
+  }
+
+  pub fn clear_tracer(&mut self) -> Option<Box<dyn Tracer<W> + Send + Sync>> {
+    self.tracer.take()
+  }
+
+  pub fn push_input(&mut self, value: W) {
+    self.input.push_back(value);
+  }
+
+  pub fn push_inputs(&mut self, values: &[W]) {
+    self.input.extend(values.iter().cloned());
+  }
+
+  // anything that's been queued up but not read by the program yet
+  pub fn pending_input(&self) -> &VecDeque<W> {
+    &self.input
+  }
+
+  // queue up the input and run until the program halts. running out of
+  // input before then is an error
+  pub fn run(&mut self, input_buffer: &[W]) -> Result<Vec<W>, VmError<W>> {
+    let output_buffer = self.run_until_input(input_buffer)?;
+
+    if !self.halted {
+      return Err(VmError::NeedsInput { ip: self.instruction_ptr });
+    }
+
+    Ok(output_buffer)
+  }
+
+  // queue up the input and run until the program halts or wants more input
+  // than it's been given. anything it didn't read stays queued for next time
+  pub fn run_until_input(&mut self, input_buffer: &[W]) -> Result<Vec<W>, VmError<W>> {
+    let mut output_buffer = Vec::new();
+
+    self.push_inputs(input_buffer);
+    self.run_with(&mut VecDeque::new(), &mut output_buffer)?;
+    Ok(output_buffer)
+  }
+
+  // run until the vm halts, or until it wants input and there isn't any.
+  // queued input is used up first, then more is read from input as needed
+  pub fn run_with<I, O>(&mut self, input: &mut I, output: &mut O) -> Result<(), VmError<W>>
+  where
+    I: Input<W> + ?Sized,
+    O: Output<W> + ?Sized,
+  {
+    loop {
+      match self.step() {
+        State::Output(x) => output.write(x),
+        State::AwaitingInput => match input.read() {
+          Some(x) => self.push_input(x),
+          None => return Ok(()),
+        },
+        State::Halted => return Ok(()),
+        State::Faulted(err) => return Err(err),
+      }
+    }
+  }
+
+  // run until something happens that the caller needs to deal with: an
+  // output, running out of queued input, halting or an error
+  pub fn step(&mut self) -> State<W> {
+    loop {
+      W::fast_forward(self);
+
+      match self.execute() {
+        Ok(None) => (),
+        Ok(Some(state)) => return state,
+        Err(err) => return State::Faulted(err),
+      }
+    }
+  }
+
+  // run a single instruction. None if nothing interesting happened
+  pub fn step_instruction(&mut self) -> Option<State<W>> {
+    match self.execute() {
+      Ok(state) => state,
+      Err(err) => Some(State::Faulted(err)),
+    }
+  }
+
+  fn execute(&mut self) -> Result<Option<State<W>>, VmError<W>> {
+    if self.halted {
+      return Ok(Some(State::Halted));
+    }
+
+    let (ip, steps) = (self.instruction_ptr, self.steps);
+    if self.budget == Some(0) {
+      return Err(VmError::BudgetExhausted { ip, steps });
+    }
+    if let Some(deadline) = self.deadline {
+      if steps.is_multiple_of(DEADLINE_CHECK_INTERVAL) && Instant::now() >= deadline {
+        return Err(VmError::DeadlineExceeded { ip, steps });
+      }
+    }
+
+    let op = self.decode()?;
+    let mut next = op.ip + op.opcode.param_count() + 1;
+    let mut state = None;
+
+    // an in with nothing to read is tried again later, so only tell the
+    // tracer about it once it can actually run
+    let waiting = op.opcode == Opcode::In && self.input.is_empty();
+    let pending = if self.tracer.is_some() && !waiting {
+      Some(self.trace_before(&op))
+    } else {
+      None
+    };
+
+    match op.opcode {
+      Opcode::Add => {
+        let x = self.get_value(&op, 0)?;
+        let y = self.get_value(&op, 1)?;
+        let addr = self.get_addr(&op, 2)?;
+
+        match x.checked_add(&y) {
+          Some(sum) => self.write(&op, sum, addr)?,
+          None => return Err(VmError::Overflow { ip: op.ip, instruction: op.instruction }),
+        }
+      },
+      Opcode::Mul => {
+        let x = self.get_value(&op, 0)?;
+        let y = self.get_value(&op, 1)?;
+        let addr = self.get_addr(&op, 2)?;
+
+        match x.checked_mul(&y) {
+          Some(product) => self.write(&op, product, addr)?,
+          None => return Err(VmError::Overflow { ip: op.ip, instruction: op.instruction }),
+        }
+      },
+      Opcode::In => {
+        let addr = self.get_addr(&op, 0)?;
+
+        if let Some(x) = self.input.front().cloned() {
+          self.write(&op, x, addr)?;
+          self.input.pop_front();
+        } else {
+          // woah buddy we ran outta input, leave the instruction ptr on
+          // the 3 opcode so it's retried next time
+          return Ok(Some(State::AwaitingInput));
+        }
+      },
+      Opcode::Out => {
+        let x = self.get_value(&op, 0)?;
+        state = Some(State::Output(x));
+      },
+      Opcode::JmpIfTrue => {
+        if !self.get_value(&op, 0)?.is_zero() {
+          let target = self.get_value(&op, 1)?;
+          next = self.to_addr(&op, target)?;
+        }
+      },
+      Opcode::JmpIfFalse => {
+        if self.get_value(&op, 0)?.is_zero() {
+          let target = self.get_value(&op, 1)?;
+          next = self.to_addr(&op, target)?;
+        }
+      },
+      Opcode::LessThan => {
+        let x = self.get_value(&op, 0)?;
+        let y = self.get_value(&op, 1)?;
+        let addr = self.get_addr(&op, 2)?;
+        self.write(&op, if x < y { W::one() } else { W::zero() }, addr)?;
+      },
+      Opcode::Equal => {
+        let x = self.get_value(&op, 0)?;
+        let y = self.get_value(&op, 1)?;
+        let addr = self.get_addr(&op, 2)?;
+        self.write(&op, if x == y { W::one() } else { W::zero() }, addr)?;
+      },
+      Opcode::AdjustBase => {
+        let offset = self.get_value(&op, 0)?;
+
+        match self.relative_base.checked_add(&offset) {
+          Some(base) => self.relative_base = base,
+          None => return Err(VmError::Overflow { ip: op.ip, instruction: op.instruction }),
+        }
+      },
+      Opcode::Halt => {
+        self.halted = true;
+        next = op.ip;
+        state = Some(State::Halted);
+      },
+    }
+
+    self.instruction_ptr = next;
+    self.steps += 1;
+    if let Some(budget) = self.budget.as_mut() {
+      *budget -= 1;
+    }
+
+    if let Some(pending) = pending {
+      self.trace_after(op.ip, pending);
+    }
+
+    Ok(state)
+  }
+
+  pub fn from_program(program: &[W]) -> VM<W> {
+    VM::from_memory(Memory::new(program))
+  }
+
+  // for picking a memory backend or limit, e.g.
+  // VM::from_memory(Memory::with_backend(Backend::Sparse, &program))
+  pub fn from_memory(mem: Memory<W>) -> VM<W> {
+    VM {
+      image_len: mem.len(),
+      mem,
+      instruction_ptr: 0,
+      relative_base: W::zero(),
+      halted: false,
+      input: VecDeque::new(),
+      tracer: None,
+      steps: 0,
+      budget: None,
+      deadline: None,
+      cache: Some(Vec::new()),
+      lenient: false,
+      fast: true,
+    }
+  }
+
+  pub fn from_snapshot(snapshot: &Snapshot<W>) -> VM<W> {
+    let mut vm = VM::from_program(&[]);
+    vm.restore(snapshot);
+    vm.image_len = snapshot.mem.len();
+    vm
+  }
+
+  pub fn is_halted(&self) -> bool {
+    self.halted
+  }
+
+  pub fn instruction_ptr(&self) -> usize {
+    self.instruction_ptr
+  }
+
+  pub fn relative_base(&self) -> &W {
+    &self.relative_base
+  }
+
+  pub fn memory(&self) -> &Memory<W> {
+    &self.mem
+  }
+
+  // anything past the end of memory is zero
+  pub fn peek(&self, addr: usize) -> W {
+    self.mem.read(addr)
+  }
+
+  pub fn poke(&mut self, addr: usize, value: W) -> Result<(), LimitExceeded> {
+    self.forget(addr);
+    self.mem.write(addr, value)
+  }
+
+  // how many words the vm was loaded with. memory can grow past this once
+  // the program writes to scratch space, but the image stays the same. a
+  // vm from a snapshot counts everything in the snapshot as its image
+  pub fn image_len(&self) -> usize {
+    self.image_len
+  }
+
+  // poke, but only inside the program image. for setting a program up
+  // before it runs, where writing past the end is almost certainly a mistake
+  pub fn patch(&mut self, addr: usize, value: W) -> Result<(), PatchError> {
+    let len = self.image_len;
+    if addr >= len {
+      return Err(PatchError::OutOfImage { addr, len });
+    }
+    Ok(self.poke(addr, value)?)
+  }
+
+  // off by default, see instruction::decode_lenient for what it allows
+  pub fn set_lenient(&mut self, lenient: bool) {
+    self.lenient = lenient;
+    if let Some(cache) = self.cache.as_mut() {
+      cache.clear();
+    }
+  }
+
+  // instructions are decoded once and remembered until something writes
+  // over them. it's on by default, turning it off is only really useful for
+  // seeing how much it helps
+  pub fn set_decode_cache(&mut self, enabled: bool) {
+    self.cache = if enabled { Some(Vec::new()) } else { None };
+  }
+
+  // on by default, and only does anything for i64 words. see fast_forward
+  pub fn set_fast_path(&mut self, enabled: bool) {
+    self.fast = enabled;
+  }
+
+  // run at most budget more instructions before giving up with
+  // VmError::BudgetExhausted. None means no limit
+  pub fn set_budget(&mut self, budget: Option<u64>) {
+    self.budget = budget;
+  }
+
+  pub fn budget(&self) -> Option<u64> {
+    self.budget
+  }
+
+  // give up with VmError::DeadlineExceeded once the deadline has passed
+  pub fn set_deadline(&mut self, deadline: Option<Instant>) {
+    self.deadline = deadline;
+  }
+
+  pub fn set_timeout(&mut self, timeout: Duration) {
+    self.deadline = Some(Instant::now() + timeout);
+  }
+
+  // how many instructions have been run, ever
+  pub fn steps(&self) -> u64 {
+    self.steps
+  }
+
+  // writes by the program at or past limit fail with VmError::MemoryLimit
+  pub fn set_memory_limit(&mut self, limit: Option<usize>) {
+    self.mem.set_limit(limit);
+  }
+
+  // save where the vm is up to. memory pages are shared with the vm until
+  // one side writes to them, so taking lots of these is cheap
+  pub fn snapshot(&self) -> Snapshot<W> {
+    Snapshot {
+      mem: self.mem.clone(),
+      instruction_ptr: self.instruction_ptr,
+      relative_base: self.relative_base.clone(),
+      halted: self.halted,
+      input: self.input.clone(),
+    }
+  }
+
+  // go back to a snapshot. the tracer, if there is one, is kept
+  pub fn restore(&mut self, snapshot: &Snapshot<W>) {
+    self.mem = snapshot.mem.clone();
+    self.instruction_ptr = snapshot.instruction_ptr;
+    self.relative_base = snapshot.relative_base.clone();
+    self.halted = snapshot.halted;
+    self.input = snapshot.input.clone();
+
+    if let Some(cache) = self.cache.as_mut() {
+      cache.clear();
+    }
+  }
+}
+
+// a fork starts off without a tracer, there's no way to share one
+impl<W: Word> Clone for VM<W> {
+  fn clone(&self) -> VM<W> {
+    let mut vm = VM::from_snapshot(&self.snapshot());
+    vm.steps = self.steps;
+    vm.budget = self.budget;
+    vm.deadline = self.deadline;
+    vm.cache = self.cache.clone();
+    vm.lenient = self.lenient;
+    vm.fast = self.fast;
+    vm.image_len = self.image_len;
+    vm
+  }
+}
+
+impl VM<i64> {
+  // what param n of the instruction at ip reads, or None for anything that
+  // execute would have to turn into an error
+  fn fast_value(&self, ip: usize, modes: &[Mode; 3], n: usize) -> Option<i64> {
+    match modes[n] {
+      Mode::Immediate => Some(self.mem.read(ip + 1 + n)),
+      _ => Some(self.mem.read(self.fast_addr(ip, modes, n)?)),
+    }
+  }
+
+  fn fast_addr(&self, ip: usize, modes: &[Mode; 3], n: usize) -> Option<usize> {
+    let value = self.mem.read(ip + 1 + n);
+    let addr = match modes[n] {
+      Mode::Position => value,
+      Mode::Relative => self.relative_base.checked_add(value)?,
+      Mode::Immediate => return None,
+    };
+    usize::try_from(addr).ok()
+  }
+
+  // the common case for i64 words with nothing watching: arithmetic,
+  // comparisons, jumps and arb run straight off memory as plain integers,
+  // without building an Op or going through the tracer, budget and decode
+  // cache. it stops at the first instruction that does anything else, or
+  // that would fail, and leaves it to execute to do properly
+  pub(crate) fn fast_forward(&mut self) {
+    if !self.fast || self.halted || self.tracer.is_some() || self.budget.is_some() || self.deadline.is_some() {
+      return;
+    }
+
+    loop {
+      let ip = self.instruction_ptr;
+      let (opcode, modes) = match decode(&self.mem.read(ip)) {
+        Ok(x) => x,
+        Err(_) => return,
+      };
+      if ip + opcode.param_count() >= self.mem.len() {
+        return;
+      }
+
+      let mut next = ip + opcode.param_count() + 1;
+      match opcode {
+        Opcode::Add | Opcode::Mul | Opcode::LessThan | Opcode::Equal => {
+          let params = (self.fast_value(ip, &modes, 0), self.fast_value(ip, &modes, 1), self.fast_addr(ip, &modes, 2));
+          let (x, y, addr) = match params {
+            (Some(x), Some(y), Some(addr)) => (x, y, addr),
+            _ => return,
+          };
+
+          let result = match opcode {
+            Opcode::Add => x.checked_add(y),
+            Opcode::Mul => x.checked_mul(y),
+            Opcode::LessThan => Some((x < y) as i64),
+            _ => Some((x == y) as i64),
+          };
+          let result = match result {
+            Some(x) => x,
+            None => return,
+          };
+
+          self.forget(addr);
+          if self.mem.write(addr, result).is_err() {
+            return;
+          }
+        },
+        Opcode::JmpIfTrue | Opcode::JmpIfFalse => {
+          let x = match self.fast_value(ip, &modes, 0) {
+            Some(x) => x,
+            None => return,
+          };
+
+          if (x != 0) == (opcode == Opcode::JmpIfTrue) {
+            match self.fast_value(ip, &modes, 1).and_then(|x| usize::try_from(x).ok()) {
+              Some(target) => next = target,
+              None => return,
+            }
+          }
+        },
+        Opcode::AdjustBase => {
+          match self.fast_value(ip, &modes, 0).and_then(|x| self.relative_base.checked_add(x)) {
+            Some(base) => self.relative_base = base,
+            None => return,
+          }
+        },
+        _ => return,
+      }
+
+      self.instruction_ptr = next;
+      self.steps += 1;
+    }
+  }
+}
+
+// everything about a vm except its tracer
+#[derive(Debug, Clone, PartialEq)]
+pub struct Snapshot<W: Word = i64> {
+  pub(crate) mem: Memory<W>,
+  pub(crate) instruction_ptr: usize,
+  pub(crate) relative_base: W,
+  pub(crate) halted: bool,
+  pub(crate) input: VecDeque<W>,
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  use num::bigint::BigInt;
+
+  use crate::fixtures::{ADDER, COMPARATOR};
+  use crate::memory::Backend;
+
+  fn words<W: Word>(program: &[i64]) -> Vec<W> {
+    program.iter().map(|x| W::from_i64(*x)).collect()
+  }
+
+  fn assert_output<W: Word>(program: &[i64], input: &[i64], expected: &[i64]) {
+    let mut vm = VM::<W>::from_program(&words(program));
+    assert_eq!(vm.run(&words(input)).unwrap(), words::<W>(expected));
+    assert!(vm.is_halted());
+  }
+
+  // the same suite runs against every word type
+  macro_rules! vm_tests {
+    ($name:ident, $word:ty) => {
+      mod $name {
+        use super::*;
+
+        #[test]
+        fn test_add_mul() {
+          let mut vm = VM::<$word>::from_program(&words(&[1, 9, 10, 3, 2, 3, 11, 0, 99, 30, 40, 50]));
+          vm.run(&[]).unwrap();
+          assert_eq!(vm.peek(0), <$word>::from_i64(3500));
+        }
+
+        #[test]
+        fn test_immediate_mode() {
+          let mut vm = VM::<$word>::from_program(&words(&[1002, 4, 3, 4, 33]));
+          vm.run(&[]).unwrap();
+          assert_eq!(vm.peek(4), <$word>::from_i64(99));
+        }
+
+        #[test]
+        fn test_compare() {
+          // position mode, input == 8
+          assert_output::<$word>(&[3, 9, 8, 9, 10, 9, 4, 9, 99, -1, 8], &[8], &[1]);
+          assert_output::<$word>(&[3, 9, 8, 9, 10, 9, 4, 9, 99, -1, 8], &[7], &[0]);
+
+          // immediate mode, input < 8
+          assert_output::<$word>(&[3, 3, 1107, -1, 8, 3, 4, 3, 99], &[7], &[1]);
+          assert_output::<$word>(&[3, 3, 1107, -1, 8, 3, 4, 3, 99], &[9], &[0]);
+        }
+
+        #[test]
+        fn test_jumps() {
+          assert_output::<$word>(&COMPARATOR, &[7], &[999]);
+          assert_output::<$word>(&COMPARATOR, &[8], &[1000]);
+          assert_output::<$word>(&COMPARATOR, &[9], &[1001]);
+        }
+
+        #[test]
+        fn test_relative_base_quine() {
+          let program = [109, 1, 204, -1, 1001, 100, 1, 100, 1008, 100, 16, 101, 1006, 101, 0, 99];
+          assert_output::<$word>(&program, &[], &program);
+        }
+
+        #[test]
+        fn test_large_numbers() {
+          assert_output::<$word>(&[1102, 34915192, 34915192, 7, 4, 7, 99, 0], &[], &[1219070632396864]);
+          assert_output::<$word>(&[104, 1125899906842624, 99], &[], &[1125899906842624]);
+        }
+
+        #[test]
+        fn test_run_until_input() {
+          // echo whatever comes in until we see a zero
+          let program = [3, 9, 4, 9, 1005, 9, 0, 99, 0, 0];
+          let mut vm = VM::<$word>::from_program(&words(&program));
+
+          assert_eq!(vm.run_until_input(&words(&[5, 6])).unwrap(), words::<$word>(&[5, 6]));
+          assert!(!vm.is_halted());
+
+          assert_eq!(vm.run_until_input(&words(&[0])).unwrap(), words::<$word>(&[0]));
+          assert!(vm.is_halted());
+        }
+      }
+    };
+  }
+
+  vm_tests!(i64_tests, i64);
+  vm_tests!(i128_tests, i128);
+  vm_tests!(bigint_tests, BigInt);
+
+  #[test]
+  fn test_bigint_past_i64() {
+    // (2^62)^2 doesn't fit in an i64, but a BigInt is fine with it
+    let program = words::<BigInt>(&[1102, 1 << 62, 1 << 62, 7, 4, 7, 99, 0]);
+    let mut vm = VM::from_program(&program);
+
+    let expected: BigInt = "21267647932558653966460912964485513216".parse().unwrap();
+    assert_eq!(vm.run(&[]).unwrap(), [expected]);
+  }
+
+  fn assert_error(program: &[i64], expected: VmError) {
+    let mut vm = VM::from_program(program);
+    assert_eq!(vm.run(&[]), Err(expected));
+    assert!(!vm.is_halted());
+  }
+
+  #[test]
+  fn test_errors() {
+    assert_error(&[1, 0, 0, 0, 42], VmError::UnknownOpcode { ip: 4, instruction: 42 });
+    assert_error(&[-1], VmError::UnknownOpcode { ip: 0, instruction: -1 });
+    assert_error(&[301, 0, 0, 0, 99], VmError::InvalidMode { ip: 0, instruction: 301, mode: 3 });
+    assert_error(&[1104, 0, 99], VmError::ExtraModeDigits { ip: 0, instruction: 1104 });
+    assert_error(&[1, -5, 0, 0, 99], VmError::NegativeAddress { ip: 0, instruction: 1, address: -5 });
+    assert_error(&[204, -1, 99], VmError::NegativeAddress { ip: 0, instruction: 204, address: -1 });
+    assert_error(&[11101, 1, 1, 0, 99], VmError::ImmediateWriteTarget { ip: 0, instruction: 11101 });
+    assert_error(&[103, 0, 99], VmError::ImmediateWriteTarget { ip: 0, instruction: 103 });
+    assert_error(&[1102, 1 << 62, 4, 0, 99], VmError::Overflow { ip: 0, instruction: 1102 });
+    assert_error(&[1106, 0, 10], VmError::PcOutOfBounds { ip: 10 });
+    assert_error(&[1101, 1, 1], VmError::PcOutOfBounds { ip: 0 });
+  }
+
+  #[test]
+  fn test_lenient() {
+    // mode 3 is position: add [0] [0] -> [0]
+    let mut vm = VM::<i64>::from_program(&[30301, 0, 0, 0, 99]);
+    vm.set_lenient(true);
+    assert_eq!(vm.run(&[]), Ok(vec![]));
+    assert_eq!(vm.peek(0), 60602);
+
+    // an immediate write target is a position
+    let mut vm = VM::<i64>::from_program(&[11101, 3, 4, 5, 99, 0]);
+    vm.set_lenient(true);
+    assert_eq!(vm.run(&[]), Ok(vec![]));
+    assert_eq!(vm.peek(5), 7);
+
+    // turning it back off makes the same program an error again
+    let mut vm = VM::<i64>::from_program(&[11101, 3, 4, 5, 99, 0]);
+    vm.set_lenient(true);
+    vm.set_lenient(false);
+    assert_eq!(vm.run(&[]), Err(VmError::ImmediateWriteTarget { ip: 0, instruction: 11101 }));
+  }
+
+  #[test]
+  fn test_error_leaves_ip_on_instruction() {
+    let mut vm = VM::<i64>::from_program(&[104, 7, 42]);
+    assert_eq!(vm.run_until_input(&[]), Err(VmError::UnknownOpcode { ip: 2, instruction: 42 }));
+    assert_eq!(vm.instruction_ptr, 2);
+  }
+
+  #[test]
+  fn test_step() {
+    // echo whatever comes in until we see a zero
+    let mut vm = VM::<i64>::from_program(&[3, 9, 4, 9, 1005, 9, 0, 99, 0, 0]);
+    vm.push_inputs(&[5, 0]);
+
+    assert_eq!(vm.step(), State::Output(5));
+    assert_eq!(vm.step(), State::Output(0));
+    assert_eq!(vm.step(), State::Halted);
+    assert_eq!(vm.step(), State::Halted);
+
+    let mut vm = VM::<i64>::from_program(&[3, 9, 4, 9, 1005, 9, 0, 99, 0, 0]);
+    vm.push_input(7);
+    assert_eq!(vm.step(), State::Output(7));
+    assert_eq!(vm.step(), State::AwaitingInput);
+    vm.push_input(0);
+    assert_eq!(vm.step(), State::Output(0));
+    assert_eq!(vm.step(), State::Halted);
+
+    let mut vm = VM::<i64>::from_program(&[104, 1, 42]);
+    assert_eq!(vm.step(), State::Output(1));
+    assert_eq!(
+      vm.step(),
+      State::Faulted(VmError::UnknownOpcode { ip: 2, instruction: 42 }),
+    );
+  }
+
+  #[test]
+  fn test_leftover_input() {
+    let mut vm = VM::<i64>::from_program(&ADDER);
+    assert_eq!(vm.run(&[1, 2, 3, 4]), Ok(vec![3]));
+    assert_eq!(vm.pending_input(), &[3, 4]);
+
+    // a half fed vm keeps hold of what it was given
+    let mut vm = VM::<i64>::from_program(&ADDER);
+    assert_eq!(vm.run_until_input(&[5]), Ok(vec![]));
+    assert!(vm.pending_input().is_empty());
+    assert_eq!(vm.run_until_input(&[6]), Ok(vec![11]));
+    assert!(vm.is_halted());
+
+    let mut vm = VM::<i64>::from_program(&ADDER);
+    assert_eq!(vm.run(&[5]), Err(VmError::NeedsInput { ip: 2 }));
+    vm.push_input(1);
+    assert_eq!(vm.run(&[]), Ok(vec![6]));
+  }
+
+  #[test]
+  fn test_backends() {
+    let quine = [109, 1, 204, -1, 1001, 100, 1, 100, 1008, 100, 16, 101, 1006, 101, 0, 99];
+    for backend in &[Backend::Dense, Backend::Paged, Backend::Sparse] {
+      let mut vm = VM::<i64>::from_memory(Memory::with_backend(*backend, &quine));
+      assert_eq!(vm.run(&[]), Ok(quine.to_vec()));
+      assert_eq!(vm.memory().backend(), *backend);
+    }
+  }
+
+  #[test]
+  fn test_memory_limit() {
+    // add #1 #1 -> [1000000000]
+    let program = [1101, 1, 1, 1_000_000_000, 99];
+
+    let mut vm = VM::<i64>::from_memory(Memory::with_backend(Backend::Sparse, &program));
+    assert_eq!(vm.run(&[]), Ok(vec![]));
+    assert_eq!(vm.peek(1_000_000_000), 2);
+
+    // the default backend copes with a far write too
+    let mut vm = VM::<i64>::from_program(&[1101, 1, 1, 1 << 50, 99]);
+    assert_eq!(vm.run(&[]), Ok(vec![]));
+    assert_eq!(vm.peek(1 << 50), 2);
+
+    let mut vm = VM::<i64>::from_memory(Memory::with_backend(Backend::Dense, &program));
+    vm.set_memory_limit(Some(1 << 20));
+    assert_eq!(
+      vm.run(&[]),
+      Err(VmError::MemoryLimit { ip: 0, instruction: 1101, address: 1_000_000_000, limit: 1 << 20 }),
+    );
+    assert_eq!(vm.memory().len(), 5);
+
+    // the limit sticks with snapshots and forks
+    assert_eq!(vm.clone().memory().limit(), Some(1 << 20));
+
+    // arb #usize::MAX, add #1 #1 -> rel[+0]
+    let top = usize::MAX as i128;
+    let mut vm = VM::<i128>::from_memory(Memory::with_backend(Backend::Sparse, &[109, top, 21101, 1, 1, 0, 99]));
+    assert_eq!(
+      vm.run(&[]),
+      Err(VmError::AddressOutOfRange { ip: 2, instruction: 21101, address: top }),
+    );
+  }
+
+  #[test]
+  fn test_budget() {
+    // loops forever: jt #1 #0
+    let mut vm = VM::<i64>::from_program(&[1105, 1, 0]);
+    vm.set_budget(Some(1000));
+    assert_eq!(vm.run(&[]), Err(VmError::BudgetExhausted { ip: 0, steps: 1000 }));
+    assert_eq!(vm.steps(), 1000);
+
+    // topping the budget up carries on where it stopped
+    vm.set_budget(Some(5));
+    assert_eq!(vm.step(), State::Faulted(VmError::BudgetExhausted { ip: 0, steps: 1005 }));
+
+    // outputs 1, 2, 3 then halts, in 4 instructions
+    let program = [104, 1, 104, 2, 104, 3, 99];
+    let mut vm = VM::<i64>::from_program(&program);
+    vm.set_budget(Some(4));
+    assert_eq!(vm.run(&[]), Ok(vec![1, 2, 3]));
+    assert_eq!(vm.budget(), Some(0));
+    assert_eq!(vm.steps(), 4);
+  }
+
+  #[test]
+  fn test_deadline() {
+    let mut vm = VM::<i64>::from_program(&[1105, 1, 0]);
+    vm.set_timeout(Duration::from_millis(20));
+
+    match vm.run(&[]) {
+      Err(VmError::DeadlineExceeded { ip: 0, steps }) => assert!(steps > 0),
+      x => panic!("expected to run out of time, got {:?}", x),
+    }
+  }
+
+  #[test]
+  fn test_self_modifying() {
+    // out #5, add #0 #99 -> [0], jt #1 #0. the second time round address 0
+    // is a hlt, which the decode cache mustn't miss
+    let program = [104, 5, 1101, 0, 99, 0, 1105, 1, 0];
+    for cache in &[true, false] {
+      let mut vm = VM::<i64>::from_program(&program);
+      vm.set_decode_cache(*cache);
+      vm.set_budget(Some(100));
+      assert_eq!(vm.run(&[]), Ok(vec![5]));
+    }
+
+    // the same goes for the host poking at it
+    let mut vm = VM::<i64>::from_program(&[104, 5, 1105, 1, 0]);
+    assert_eq!(vm.step(), State::Output(5));
+    vm.poke(0, 99).unwrap();
+    assert_eq!(vm.step(), State::Halted);
+  }
+
+  #[test]
+  fn test_fast_path() {
+    // the fast path gives the same answers as the long way round, including
+    // for the self modifying program above and an overflow part way through
+    let programs: [&[i64]; 3] = [
+      &[109, 1, 204, -1, 1001, 100, 1, 100, 1008, 100, 16, 101, 1006, 101, 0, 99],
+      &[104, 5, 1101, 0, 99, 0, 1105, 1, 0],
+      &[1101, 3, 0, 9, 1002, 9, 3, 9, 1105, 1, 4],
+    ];
+    for program in &programs {
+      let mut slow = VM::<i64>::from_program(program);
+      slow.set_fast_path(false);
+      let mut fast = VM::<i64>::from_program(program);
+
+      assert_eq!(fast.run(&[]), slow.run(&[]));
+      assert_eq!(fast.steps(), slow.steps());
+      assert_eq!(fast.snapshot(), slow.snapshot());
+    }
+  }
+
+  #[test]
+  fn test_far_jump() {
+    // add #99 #0 -> [2^34], jt #1 #2^34. the hlt that far out is decoded
+    // without the cache growing to cover everything before it
+    let program = [1101, 99, 0, 1 << 34, 1105, 1, 1 << 34];
+    for fast in &[true, false] {
+      let mut vm = VM::<i64>::from_memory(Memory::with_backend(Backend::Sparse, &program));
+      vm.set_fast_path(*fast);
+      assert_eq!(vm.run(&[]), Ok(vec![]));
+      assert_eq!(vm.instruction_ptr(), 1 << 34);
+    }
+  }
+
+  #[test]
+  fn test_snapshot_restore() {
+    let mut vm = VM::<i64>::from_program(&ADDER);
+    assert_eq!(vm.run_until_input(&[5]), Ok(vec![]));
+
+    // fork after the first input and try a few second ones
+    let snapshot = vm.snapshot();
+    for x in 1..4 {
+      vm.restore(&snapshot);
+      assert_eq!(vm.run(&[x]), Ok(vec![5 + x]));
+    }
+
+    vm.restore(&snapshot);
+    assert_eq!(vm.instruction_ptr(), 2);
+    assert!(!vm.is_halted());
+    assert_eq!(vm.peek(13), 0);
+  }
+
+  #[test]
+  fn test_clone() {
+    let mut vm = VM::<i64>::from_program(&ADDER);
+    vm.push_inputs(&[5, 6, 7]);
+
+    let mut fork = vm.clone();
+    // turn the fork's add into a mul
+    fork.poke(4, 2).unwrap();
+    assert_eq!(vm.run(&[]), Ok(vec![11]));
+    assert_eq!(fork.run(&[]), Ok(vec![30]));
+    assert_eq!(fork.pending_input(), &[7]);
+    assert_eq!(vm.peek(4), 1);
+  }
+}
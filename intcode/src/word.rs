@@ -0,0 +1,140 @@
+use std::fmt;
+use std::str::FromStr;
+
+use num::bigint::BigInt;
+use num::{ToPrimitive, Zero};
+
+use crate::vm::VM;
+
+// a single memory cell. plain i64 is plenty for most programs, i128 and
+// BigInt are there for the ones that like to multiply huge numbers together
+pub trait Word: Clone + PartialEq + PartialOrd + fmt::Debug + fmt::Display + FromStr {
+  fn from_i64(value: i64) -> Self;
+  fn to_i64(&self) -> Option<i64>;
+  fn to_usize(&self) -> Option<usize>;
+
+  fn checked_add(&self, other: &Self) -> Option<Self>;
+  fn checked_mul(&self, other: &Self) -> Option<Self>;
+
+  // little endian two's complement, as short as it'll go (zero is no bytes
+  // at all). from_bytes is None if the value doesn't fit
+  fn to_bytes(&self) -> Vec<u8>;
+  fn from_bytes(bytes: &[u8]) -> Option<Self>;
+
+  // run as much of vm as can be done without going through the general
+  // purpose step, see VM::<i64>::fast_forward. only i64 has a fast path, so
+  // the default does nothing
+  fn fast_forward(_vm: &mut VM<Self>) {}
+
+  fn zero() -> Self {
+    Self::from_i64(0)
+  }
+  fn one() -> Self {
+    Self::from_i64(1)
+  }
+  fn is_zero(&self) -> bool {
+    *self == Self::zero()
+  }
+}
+
+// fast is what to use for Word::fast_forward, if the type has one
+macro_rules! impl_word_for_primitive {
+  ($t:ty) => {
+    impl_word_for_primitive!($t, |_| ());
+  };
+  ($t:ty, $fast:expr) => {
+    impl Word for $t {
+      fn from_i64(value: i64) -> Self {
+        value as $t
+      }
+      fn to_i64(&self) -> Option<i64> {
+        ToPrimitive::to_i64(self)
+      }
+      fn to_usize(&self) -> Option<usize> {
+        ToPrimitive::to_usize(self)
+      }
+
+      fn checked_add(&self, other: &Self) -> Option<Self> {
+        <$t>::checked_add(*self, *other)
+      }
+      fn checked_mul(&self, other: &Self) -> Option<Self> {
+        <$t>::checked_mul(*self, *other)
+      }
+
+      fn to_bytes(&self) -> Vec<u8> {
+        let bytes = self.to_le_bytes();
+        let negative = *self < 0;
+        let fill = if negative { 0xff } else { 0 };
+
+        // drop the top bytes that are just sign, as long as the sign bit
+        // of whatever's left still says the right thing
+        let mut len = bytes.len();
+        while len > 1 && bytes[len - 1] == fill && (bytes[len - 2] & 0x80 != 0) == negative {
+          len -= 1;
+        }
+
+        if len == 1 && bytes[0] == 0 {
+          len = 0;
+        }
+        bytes[..len].to_vec()
+      }
+      fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let mut buf = [0u8; std::mem::size_of::<$t>()];
+        if bytes.len() > buf.len() {
+          return None;
+        }
+
+        if bytes.last().is_some_and(|x| x & 0x80 != 0) {
+          buf = [0xff; std::mem::size_of::<$t>()];
+        }
+        buf[..bytes.len()].copy_from_slice(bytes);
+        Some(<$t>::from_le_bytes(buf))
+      }
+
+      fn is_zero(&self) -> bool {
+        *self == 0
+      }
+
+      fn fast_forward(vm: &mut VM<Self>) {
+        ($fast)(vm)
+      }
+    }
+  };
+}
+
+impl_word_for_primitive!(i64, VM::fast_forward);
+impl_word_for_primitive!(i128);
+
+impl Word for BigInt {
+  fn from_i64(value: i64) -> Self {
+    BigInt::from(value)
+  }
+  fn to_i64(&self) -> Option<i64> {
+    ToPrimitive::to_i64(self)
+  }
+  fn to_usize(&self) -> Option<usize> {
+    ToPrimitive::to_usize(self)
+  }
+
+  // bigints never overflow
+  fn checked_add(&self, other: &Self) -> Option<Self> {
+    Some(self + other)
+  }
+  fn checked_mul(&self, other: &Self) -> Option<Self> {
+    Some(self * other)
+  }
+
+  fn to_bytes(&self) -> Vec<u8> {
+    if Zero::is_zero(self) {
+      return Vec::new();
+    }
+    self.to_signed_bytes_le()
+  }
+  fn from_bytes(bytes: &[u8]) -> Option<Self> {
+    Some(BigInt::from_signed_bytes_le(bytes))
+  }
+
+  fn is_zero(&self) -> bool {
+    Zero::is_zero(self)
+  }
+}